@@ -0,0 +1,501 @@
+use global_hotkey::hotkey::{Code, HotKey, Modifiers};
+use serde::Serialize;
+use std::fmt;
+
+// Why an accelerator string could not be turned into a HotKey.
+// Serialized with a `kind` tag so the frontend can point at the exact problem
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HotkeyParseError {
+    UnknownKey { key: String },
+    DuplicateModifier { modifier: String },
+    MultipleKeys { first: String, second: String },
+    MissingKey,
+    ModifierOnly,
+    ChordTooLong { steps: usize },
+    UnknownTrigger { trigger: String },
+    TriggerOnChord,
+}
+
+impl fmt::Display for HotkeyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HotkeyParseError::UnknownKey { key } => {
+                write!(f, "The key '{}' is not supported as a hotkey. Please create a new issue in Github.", key)
+            }
+            HotkeyParseError::DuplicateModifier { modifier } => write!(f, "The modifier '{}' is used more than once", modifier),
+            HotkeyParseError::MultipleKeys { first, second } => {
+                write!(f, "Only one non-modifier key is allowed, found '{}' and '{}'", first, second)
+            }
+            HotkeyParseError::MissingKey => write!(f, "The hotkey is empty or has an empty part"),
+            HotkeyParseError::ModifierOnly => write!(f, "The hotkey needs a key besides the modifiers"),
+            HotkeyParseError::ChordTooLong { steps } => {
+                write!(f, "A chord can only have a leader and one follow-up key, found {} steps", steps)
+            }
+            HotkeyParseError::UnknownTrigger { trigger } => {
+                write!(f, "Unknown trigger '@{}', use @tap, @double or @hold", trigger)
+            }
+            HotkeyParseError::TriggerOnChord => write!(f, "Chords cannot use @double or @hold"),
+        }
+    }
+}
+
+impl std::error::Error for HotkeyParseError {}
+
+// Parses accelerators such as "CTRL + ALT + P" into a HotKey
+pub fn parse_hotkey(hotkey_str: &str) -> Result<HotKey, HotkeyParseError> {
+    if hotkey_str.trim().is_empty() {
+        return Err(HotkeyParseError::MissingKey);
+    }
+
+    let mut modifiers = Modifiers::empty();
+    let mut key: Option<&str> = None;
+
+    for part in hotkey_str.split('+').map(|s| s.trim()) {
+        if part.is_empty() {
+            return Err(HotkeyParseError::MissingKey);
+        }
+
+        if let Some(modifier) = parse_modifier(part) {
+            if modifiers.contains(modifier) {
+                return Err(HotkeyParseError::DuplicateModifier {
+                    modifier: part.to_string(),
+                });
+            }
+            modifiers |= modifier;
+        } else if let Some(first) = key {
+            return Err(HotkeyParseError::MultipleKeys {
+                first: first.to_string(),
+                second: part.to_string(),
+            });
+        } else {
+            key = Some(part);
+        }
+    }
+
+    let key = key.ok_or(HotkeyParseError::ModifierOnly)?;
+    let code = <Code as CodeExt>::from_str(key)?;
+    Ok(HotKey::new(Some(modifiers), code))
+}
+
+// Canonical form of a HotKey: modifiers in a stable order followed by the canonical key name,
+// e.g. "CTRL + ALT + SHIFT + META + Home". parse_hotkey(format_hotkey(x)) gives back x
+pub fn format_hotkey(hotkey: &HotKey) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    if hotkey.mods.contains(Modifiers::CONTROL) {
+        parts.push("CTRL");
+    }
+    if hotkey.mods.contains(Modifiers::ALT) {
+        parts.push("ALT");
+    }
+    if hotkey.mods.contains(Modifiers::SHIFT) {
+        parts.push("SHIFT");
+    }
+    if hotkey.mods.contains(Modifiers::SUPER) || hotkey.mods.contains(Modifiers::META) {
+        parts.push("META");
+    }
+
+    let key = hotkey.key.name();
+    parts.push(&key);
+    parts.join(" + ")
+}
+
+// Separates the leader combo of a chord from its follow-up key, e.g. "CTRL + ALT + S > N"
+pub const CHORD_SEPARATOR: char = '>';
+
+// Separates a binding from its press pattern, e.g. "CTRL + P @double"
+pub const TRIGGER_SEPARATOR: char = '@';
+
+// Press pattern a binding reacts to. Tap is the default and is written without a suffix
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    #[default]
+    Tap,
+    DoubleTap,
+    Hold,
+}
+
+impl Trigger {
+    fn parse(s: &str) -> Result<Self, HotkeyParseError> {
+        match s.to_lowercase().as_str() {
+            "tap" => Ok(Trigger::Tap),
+            "double" => Ok(Trigger::DoubleTap),
+            "hold" => Ok(Trigger::Hold),
+            _ => Err(HotkeyParseError::UnknownTrigger { trigger: s.to_string() }),
+        }
+    }
+
+    fn suffix(self) -> Option<&'static str> {
+        match self {
+            Trigger::Tap => None,
+            Trigger::DoubleTap => Some("double"),
+            Trigger::Hold => Some("hold"),
+        }
+    }
+}
+
+// A single hotkey, or a leader hotkey that arms a chord which `then` completes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub hotkey: HotKey,
+    pub then: Option<HotKey>,
+    pub trigger: Trigger,
+}
+
+impl Binding {
+    pub fn single(hotkey: HotKey) -> Self {
+        Self { hotkey, then: None, trigger: Trigger::Tap }
+    }
+}
+
+// Parses a binding: a plain accelerator with an optional "@double" or "@hold" suffix,
+// or a two step chord such as "CTRL + ALT + S > N"
+pub fn parse_binding(binding_str: &str) -> Result<Binding, HotkeyParseError> {
+    let (binding_str, trigger) = match binding_str.rsplit_once(TRIGGER_SEPARATOR) {
+        Some((binding_str, trigger)) => (binding_str, Trigger::parse(trigger.trim())?),
+        None => (binding_str, Trigger::Tap),
+    };
+
+    let steps: Vec<&str> = binding_str.split(CHORD_SEPARATOR).collect();
+    match steps.as_slice() {
+        [hotkey] => Ok(Binding { trigger, ..Binding::single(parse_hotkey(hotkey)?) }),
+        [_, _] if trigger != Trigger::Tap => Err(HotkeyParseError::TriggerOnChord),
+        [leader, then] => Ok(Binding {
+            hotkey: parse_hotkey(leader)?,
+            then: Some(parse_hotkey(then)?),
+            trigger,
+        }),
+        _ => Err(HotkeyParseError::ChordTooLong { steps: steps.len() }),
+    }
+}
+
+// Canonical form of a Binding, chord steps are joined with " > " and non-tap triggers get a " @double" style suffix
+pub fn format_binding(binding: &Binding) -> String {
+    let formatted = match &binding.then {
+        Some(then) => format!("{} {} {}", format_hotkey(&binding.hotkey), CHORD_SEPARATOR, format_hotkey(then)),
+        None => format_hotkey(&binding.hotkey),
+    };
+    match binding.trigger.suffix() {
+        Some(suffix) => format!("{} {}{}", formatted, TRIGGER_SEPARATOR, suffix),
+        None => formatted,
+    }
+}
+
+fn parse_modifier(part: &str) -> Option<Modifiers> {
+    match part.to_uppercase().as_str() {
+        "CTRL" | "CONTROL" => Some(Modifiers::CONTROL),
+        "ALT" | "OPTION" => Some(Modifiers::ALT),
+        "SHIFT" => Some(Modifiers::SHIFT),
+        "META" | "COMMAND" | "CMD" | "SUPER" | "WIN" => Some(Modifiers::META),
+        _ => None,
+    }
+}
+
+// Every key that can be used in a hotkey, with its canonical name followed by extra aliases.
+// Names are matched case-insensitively. The UI gets the names through get_supported_keys, and a test checks that
+// the README lists all of them
+const SUPPORTED_KEYS: &[(Code, &str, &[&str])] = &[
+    // Digits
+    (Code::Digit0, "0", &["Digit0"]),
+    (Code::Digit1, "1", &["Digit1"]),
+    (Code::Digit2, "2", &["Digit2"]),
+    (Code::Digit3, "3", &["Digit3"]),
+    (Code::Digit4, "4", &["Digit4"]),
+    (Code::Digit5, "5", &["Digit5"]),
+    (Code::Digit6, "6", &["Digit6"]),
+    (Code::Digit7, "7", &["Digit7"]),
+    (Code::Digit8, "8", &["Digit8"]),
+    (Code::Digit9, "9", &["Digit9"]),
+    // Letters
+    (Code::KeyA, "A", &["KeyA"]),
+    (Code::KeyB, "B", &["KeyB"]),
+    (Code::KeyC, "C", &["KeyC"]),
+    (Code::KeyD, "D", &["KeyD"]),
+    (Code::KeyE, "E", &["KeyE"]),
+    (Code::KeyF, "F", &["KeyF"]),
+    (Code::KeyG, "G", &["KeyG"]),
+    (Code::KeyH, "H", &["KeyH"]),
+    (Code::KeyI, "I", &["KeyI"]),
+    (Code::KeyJ, "J", &["KeyJ"]),
+    (Code::KeyK, "K", &["KeyK"]),
+    (Code::KeyL, "L", &["KeyL"]),
+    (Code::KeyM, "M", &["KeyM"]),
+    (Code::KeyN, "N", &["KeyN"]),
+    (Code::KeyO, "O", &["KeyO"]),
+    (Code::KeyP, "P", &["KeyP"]),
+    (Code::KeyQ, "Q", &["KeyQ"]),
+    (Code::KeyR, "R", &["KeyR"]),
+    (Code::KeyS, "S", &["KeyS"]),
+    (Code::KeyT, "T", &["KeyT"]),
+    (Code::KeyU, "U", &["KeyU"]),
+    (Code::KeyV, "V", &["KeyV"]),
+    (Code::KeyW, "W", &["KeyW"]),
+    (Code::KeyX, "X", &["KeyX"]),
+    (Code::KeyY, "Y", &["KeyY"]),
+    (Code::KeyZ, "Z", &["KeyZ"]),
+    // Punctuation
+    (Code::Minus, "-", &["Minus"]),
+    (Code::Equal, "=", &["Equal"]),
+    (Code::Slash, "/", &["Slash"]),
+    (Code::Backslash, "\\", &["Backslash"]),
+    (Code::Semicolon, ";", &["Semicolon"]),
+    (Code::Quote, "'", &["Quote"]),
+    (Code::Comma, ",", &["Comma"]),
+    (Code::Period, ".", &["Period"]),
+    (Code::BracketLeft, "[", &["BracketLeft"]),
+    (Code::BracketRight, "]", &["BracketRight"]),
+    (Code::Backquote, "`", &["Backquote"]),
+    // Navigation and editing
+    (Code::Space, "Space", &[]),
+    (Code::Enter, "Enter", &["Return"]),
+    (Code::Tab, "Tab", &[]),
+    (Code::Backspace, "Backspace", &[]),
+    (Code::Escape, "Escape", &["Esc"]),
+    (Code::Delete, "Delete", &["Del"]),
+    (Code::Insert, "Insert", &["Ins"]),
+    (Code::Home, "Home", &[]),
+    (Code::End, "End", &[]),
+    (Code::PageUp, "PageUp", &["PgUp"]),
+    (Code::PageDown, "PageDown", &["PgDn"]),
+    (Code::ArrowUp, "ArrowUp", &["Up"]),
+    (Code::ArrowDown, "ArrowDown", &["Down"]),
+    (Code::ArrowLeft, "ArrowLeft", &["Left"]),
+    (Code::ArrowRight, "ArrowRight", &["Right"]),
+    (Code::PrintScreen, "PrintScreen", &["PrtSc"]),
+    (Code::ScrollLock, "ScrollLock", &[]),
+    (Code::Pause, "Pause", &[]),
+    (Code::CapsLock, "CapsLock", &[]),
+    (Code::NumLock, "NumLock", &[]),
+    // Function keys
+    (Code::F1, "F1", &[]),
+    (Code::F2, "F2", &[]),
+    (Code::F3, "F3", &[]),
+    (Code::F4, "F4", &[]),
+    (Code::F5, "F5", &[]),
+    (Code::F6, "F6", &[]),
+    (Code::F7, "F7", &[]),
+    (Code::F8, "F8", &[]),
+    (Code::F9, "F9", &[]),
+    (Code::F10, "F10", &[]),
+    (Code::F11, "F11", &[]),
+    (Code::F12, "F12", &[]),
+    (Code::F13, "F13", &[]),
+    (Code::F14, "F14", &[]),
+    (Code::F15, "F15", &[]),
+    (Code::F16, "F16", &[]),
+    (Code::F17, "F17", &[]),
+    (Code::F18, "F18", &[]),
+    (Code::F19, "F19", &[]),
+    (Code::F20, "F20", &[]),
+    (Code::F21, "F21", &[]),
+    (Code::F22, "F22", &[]),
+    (Code::F23, "F23", &[]),
+    (Code::F24, "F24", &[]),
+    // Numpad
+    (Code::Numpad0, "Numpad0", &["Num0"]),
+    (Code::Numpad1, "Numpad1", &["Num1"]),
+    (Code::Numpad2, "Numpad2", &["Num2"]),
+    (Code::Numpad3, "Numpad3", &["Num3"]),
+    (Code::Numpad4, "Numpad4", &["Num4"]),
+    (Code::Numpad5, "Numpad5", &["Num5"]),
+    (Code::Numpad6, "Numpad6", &["Num6"]),
+    (Code::Numpad7, "Numpad7", &["Num7"]),
+    (Code::Numpad8, "Numpad8", &["Num8"]),
+    (Code::Numpad9, "Numpad9", &["Num9"]),
+    (Code::NumpadAdd, "NumpadAdd", &["NumAdd"]),
+    (Code::NumpadSubtract, "NumpadSubtract", &["NumSubtract"]),
+    (Code::NumpadMultiply, "NumpadMultiply", &["NumMultiply"]),
+    (Code::NumpadDivide, "NumpadDivide", &["NumDivide"]),
+    (Code::NumpadDecimal, "NumpadDecimal", &["NumDecimal"]),
+    (Code::NumpadEnter, "NumpadEnter", &["NumEnter"]),
+    (Code::NumpadEqual, "NumpadEqual", &["NumEqual"]),
+    // Media keys
+    (Code::MediaPlayPause, "MediaPlayPause", &["PlayPause"]),
+    (Code::MediaPlay, "MediaPlay", &[]),
+    (Code::MediaPause, "MediaPause", &[]),
+    (Code::MediaStop, "MediaStop", &[]),
+    (Code::MediaTrackNext, "MediaTrackNext", &["MediaNextTrack", "NextTrack"]),
+    (Code::MediaTrackPrevious, "MediaTrackPrevious", &["MediaPrevTrack", "PrevTrack"]),
+    (Code::MediaFastForward, "MediaFastForward", &[]),
+    (Code::MediaRewind, "MediaRewind", &[]),
+    (Code::AudioVolumeUp, "AudioVolumeUp", &["VolumeUp"]),
+    (Code::AudioVolumeDown, "AudioVolumeDown", &["VolumeDown"]),
+    (Code::AudioVolumeMute, "AudioVolumeMute", &["VolumeMute", "Mute"]),
+];
+
+// Canonical names of every supported key, in the order of SUPPORTED_KEYS
+pub fn supported_key_names() -> Vec<&'static str> {
+    SUPPORTED_KEYS.iter().map(|(_, name, _)| *name).collect()
+}
+
+const MODIFIERS: [Modifiers; 4] = [Modifiers::CONTROL, Modifiers::ALT, Modifiers::SHIFT, Modifiers::SUPER];
+
+// Every subset of the four modifiers
+fn modifier_combinations() -> Vec<Modifiers> {
+    (0..1 << MODIFIERS.len())
+        .map(|mask: usize| {
+            MODIFIERS
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .fold(Modifiers::empty(), |mods, (_, m)| mods | *m)
+        })
+        .collect()
+}
+
+// Keys that type or edit text. Digits, letters and punctuation all have single character names
+fn is_typing_key(code: Code) -> bool {
+    let name = code.name();
+    name.chars().count() == 1
+        || name.starts_with("Numpad")
+        || matches!(
+            code,
+            Code::Space | Code::Enter | Code::Tab | Code::Backspace | Code::Delete | Code::CapsLock | Code::NumLock
+        )
+}
+
+// Every supported key with every modifier combination, i.e. everything a binding can be made of
+pub fn supported_hotkeys() -> Vec<HotKey> {
+    let modifiers = modifier_combinations();
+    SUPPORTED_KEYS
+        .iter()
+        .flat_map(|(code, _, _)| modifiers.iter().map(|mods| HotKey::new(Some(*mods), *code)))
+        .collect()
+}
+
+// Combos the recorder grabs from the OS while it waits for one. Typing keys are only taken together with CTRL, ALT
+// or META, so typing in other apps keeps working during a recording. Bare Escape is always taken since it cancels
+pub fn recordable_hotkeys() -> Vec<HotKey> {
+    supported_hotkeys()
+        .into_iter()
+        .filter(|hotkey| !is_typing_key(hotkey.key) || hotkey.mods.intersects(Modifiers::CONTROL | Modifiers::ALT | Modifiers::SUPER))
+        .collect()
+}
+
+//Extension trait to parse Code from string and back
+trait CodeExt {
+    fn from_str(s: &str) -> Result<Code, HotkeyParseError>;
+    fn name(&self) -> String;
+}
+
+impl CodeExt for Code {
+    fn from_str(s: &str) -> Result<Code, HotkeyParseError> {
+        SUPPORTED_KEYS
+            .iter()
+            .find(|(_, name, aliases)| {
+                name.eq_ignore_ascii_case(s) || aliases.iter().any(|alias| alias.eq_ignore_ascii_case(s))
+            })
+            .map(|(code, _, _)| *code)
+            .ok_or_else(|| HotkeyParseError::UnknownKey { key: s.to_string() })
+    }
+
+    // Falls back to the keyboard-types name for codes outside SUPPORTED_KEYS
+    fn name(&self) -> String {
+        SUPPORTED_KEYS
+            .iter()
+            .find(|(code, _, _)| code == self)
+            .map(|(_, name, _)| name.to_string())
+            .unwrap_or_else(|| self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_then_parse_round_trips_every_supported_hotkey() {
+        for hotkey in supported_hotkeys() {
+            let formatted = format_hotkey(&hotkey);
+            assert_eq!(parse_hotkey(&formatted), Ok(hotkey), "round trip failed for '{}'", formatted);
+        }
+    }
+
+    #[test]
+    fn supported_hotkeys_have_unique_ids() {
+        let hotkeys = supported_hotkeys();
+        let ids: std::collections::HashSet<u32> = hotkeys.iter().map(HotKey::id).collect();
+        assert_eq!(ids.len(), hotkeys.len());
+        assert_eq!(hotkeys.len(), SUPPORTED_KEYS.len() * 16);
+    }
+
+    #[test]
+    fn recording_leaves_typing_to_other_apps() {
+        let hotkeys = recordable_hotkeys();
+        let recordable = |accelerator: &str| hotkeys.contains(&parse_hotkey(accelerator).unwrap());
+        for typing in ["A", "SHIFT + A", "7", "SHIFT + /", "Space", "Enter", "Backspace", "Numpad5", "SHIFT + Tab"] {
+            assert!(!recordable(typing), "{}", typing);
+        }
+        for combo in ["CTRL + A", "ALT + SHIFT + 7", "META + Space", "F5", "SHIFT + F13", "MediaPlayPause", "PageDown", "Escape"] {
+            assert!(recordable(combo), "{}", combo);
+        }
+    }
+
+    #[test]
+    fn aliases_format_to_canonical_name() {
+        for (code, name, aliases) in SUPPORTED_KEYS {
+            for alias in aliases.iter().chain(std::iter::once(name)) {
+                for input in [alias.to_string(), alias.to_lowercase(), alias.to_uppercase()] {
+                    let hotkey = parse_hotkey(&format!("ctrl+{}", input)).unwrap();
+                    assert_eq!(hotkey.key, *code);
+                    assert_eq!(format_hotkey(&hotkey), format!("CTRL + {}", name));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn modifiers_are_formatted_in_stable_order() {
+        let hotkey = parse_hotkey("cmd + Shift + alt + control + p").unwrap();
+        assert_eq!(format_hotkey(&hotkey), "CTRL + ALT + SHIFT + META + P");
+    }
+
+    #[test]
+    fn supported_key_names_are_unique() {
+        let mut names: Vec<String> = SUPPORTED_KEYS
+            .iter()
+            .flat_map(|(_, name, aliases)| aliases.iter().chain(std::iter::once(name)))
+            .map(|name| name.to_uppercase())
+            .collect();
+        let total = names.len();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), total);
+    }
+
+    // The README lists digits, letters and function keys as ranges and every other key by its quoted name
+    #[test]
+    fn readme_lists_every_supported_key() {
+        let readme = include_str!("../../README.md");
+        let missing: Vec<&str> = supported_key_names()
+            .into_iter()
+            .filter(|name| !(name.len() == 1 && name.chars().all(|c| c.is_ascii_alphanumeric())))
+            .filter(|name| !(name.starts_with('F') && name[1..].parse::<u8>().is_ok()))
+            .filter(|name| !readme.contains(&format!("{:?}", name)))
+            .collect();
+        assert!(missing.is_empty(), "README.md does not list these supported keys: {:?}", missing);
+    }
+
+    #[test]
+    fn chords_round_trip_and_reject_extra_steps() {
+        let binding = parse_binding("ctrl+alt+s>n").unwrap();
+        assert_eq!(format_binding(&binding), "CTRL + ALT + S > N");
+        assert_eq!(parse_binding(&format_binding(&binding)), Ok(binding));
+        assert_eq!(parse_binding("CTRL + S"), Ok(Binding::single(parse_hotkey("CTRL + S").unwrap())));
+        assert_eq!(parse_binding("CTRL + S > "), Err(HotkeyParseError::MissingKey));
+        assert_eq!(parse_binding("A > B > C"), Err(HotkeyParseError::ChordTooLong { steps: 3 }));
+    }
+
+    #[test]
+    fn triggers_round_trip_and_are_rejected_on_chords() {
+        let binding = parse_binding("ctrl+p@Double").unwrap();
+        assert_eq!(binding.trigger, Trigger::DoubleTap);
+        assert_eq!(format_binding(&binding), "CTRL + P @double");
+        assert_eq!(parse_binding(&format_binding(&binding)), Ok(binding));
+        assert_eq!(format_binding(&parse_binding("CTRL + P @tap").unwrap()), "CTRL + P");
+        assert_eq!(parse_binding("CTRL + P @hold").unwrap().trigger, Trigger::Hold);
+        assert_eq!(parse_binding("CTRL + P @triple"), Err(HotkeyParseError::UnknownTrigger { trigger: "triple".to_string() }));
+        assert_eq!(parse_binding("CTRL + S > N @hold"), Err(HotkeyParseError::TriggerOnChord));
+    }
+}
@@ -0,0 +1,247 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use tauri::{AppHandle, Manager};
+
+use crate::api::{
+    change_volume, like_track, next_track, play_pause, prev_track, seek_backward, seek_forward, seek_relative,
+    volume_control_down, volume_control_up, AuthResult,
+};
+use crate::hotkey::toggle_hotkeys_suspended;
+use crate::macros::run_macro_action;
+use crate::profile::cycle_profile;
+
+// Every playback action that can be bound to a global hotkey.
+// Serialized as its name ("play_pause", "next_track", "macro:3", ...) which is also the key format of the hotkey cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    PlayPause,
+    NextTrack,
+    PrevTrack,
+    VolumeUp,
+    VolumeDown,
+    SeekForward,
+    SeekBackward,
+    LikeTrack,
+    CycleProfile,
+    ToggleSuspend,
+    // User defined sequence of steps, see macros.rs
+    Macro(u32),
+}
+
+impl Action {
+    // Registry of every built-in action. A new action needs a variant, an entry here, arms in `name`, `label` and
+    // `execute`, a look at the capability predicates below, and an entry in `builtInActions` in App.vue.
+    // Macros are not listed since they are created by the user
+    pub const ALL: [Action; 10] = [
+        Action::PlayPause,
+        Action::NextTrack,
+        Action::PrevTrack,
+        Action::VolumeUp,
+        Action::VolumeDown,
+        Action::SeekForward,
+        Action::SeekBackward,
+        Action::LikeTrack,
+        Action::CycleProfile,
+        Action::ToggleSuspend,
+    ];
+
+    const MACRO_PREFIX: &'static str = "macro:";
+
+    // Name used in the hotkey cache and by the frontend
+    pub fn name(self) -> String {
+        let name = match self {
+            Action::PlayPause => "play_pause",
+            Action::NextTrack => "next_track",
+            Action::PrevTrack => "prev_track",
+            Action::VolumeUp => "volume_up",
+            Action::VolumeDown => "volume_down",
+            Action::SeekForward => "seek_forward",
+            Action::SeekBackward => "seek_backward",
+            Action::LikeTrack => "like_track",
+            Action::CycleProfile => "cycle_profile",
+            Action::ToggleSuspend => "toggle_suspend",
+            Action::Macro(id) => return format!("{}{}", Self::MACRO_PREFIX, id),
+        };
+        name.to_string()
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(id) = name.strip_prefix(Self::MACRO_PREFIX) {
+            return id.parse().ok().map(Action::Macro);
+        }
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    // Human readable name used in logs
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::PlayPause => "play/pause",
+            Action::NextTrack => "next track",
+            Action::PrevTrack => "prev track",
+            Action::VolumeUp => "volume up",
+            Action::VolumeDown => "volume down",
+            Action::SeekForward => "seek forward",
+            Action::SeekBackward => "seek backward",
+            Action::LikeTrack => "like track",
+            Action::CycleProfile => "cycle profile",
+            Action::ToggleSuspend => "suspend/resume hotkeys",
+            Action::Macro(_) => "macro",
+        }
+    }
+
+    // Global actions keep their bindings when switching profiles, otherwise you could switch into a profile you can't leave
+    pub fn is_global(self) -> bool {
+        matches!(self, Action::CycleProfile | Action::ToggleSuspend)
+    }
+
+    // Holding a plain binding of these repeats the action, see KeyRepeater
+    pub fn is_repeatable(self) -> bool {
+        matches!(self, Action::VolumeUp | Action::VolumeDown | Action::SeekForward | Action::SeekBackward)
+    }
+
+    // How a queued action absorbs a later one: 1 when their steps add up, -1 when they cancel out, None when both have to run
+    pub fn coalesce(self, later: Action) -> Option<i32> {
+        use Action::*;
+        match (self, later) {
+            (NextTrack, NextTrack)
+            | (PrevTrack, PrevTrack)
+            | (VolumeUp, VolumeUp)
+            | (VolumeDown, VolumeDown)
+            | (SeekForward, SeekForward)
+            | (SeekBackward, SeekBackward) => Some(1),
+            (VolumeUp, VolumeDown) | (VolumeDown, VolumeUp) | (SeekForward, SeekBackward) | (SeekBackward, SeekForward) => Some(-1),
+            _ => None,
+        }
+    }
+
+    // Actions that call the Spotify API go through the action queue, the others only change local state and run right away
+    // Macros run as their own task since they can wait between steps, each of their steps is queued on its own
+    pub fn uses_spotify(self) -> bool {
+        !matches!(self, Action::CycleProfile | Action::ToggleSuspend | Action::Macro(_))
+    }
+
+    // Stays registered while hotkeys are suspended, so the toggle can also resume them
+    pub fn is_suspend_exempt(self) -> bool {
+        matches!(self, Action::ToggleSuspend)
+    }
+
+    // Runs the executor registered for this action
+    pub async fn execute(self, app_handle: &AppHandle) -> Result<AuthResult, String> {
+        let state = app_handle.state();
+        match self {
+            Action::PlayPause => play_pause(state).await,
+            Action::NextTrack => next_track(state).await,
+            Action::PrevTrack => prev_track(state).await,
+            Action::VolumeUp => volume_control_up(state).await,
+            Action::VolumeDown => volume_control_down(state).await,
+            Action::SeekForward => seek_forward(state).await,
+            Action::SeekBackward => seek_backward(state).await,
+            Action::LikeTrack => like_track(state).await,
+            Action::CycleProfile => cycle_profile(app_handle).await,
+            Action::ToggleSuspend => {
+                let suspended = toggle_hotkeys_suspended(app_handle).await?;
+                Ok(AuthResult::Success {
+                    ok: if suspended { "Hotkeys suspended" } else { "Hotkeys resumed" }.to_string(),
+                })
+            }
+            // Boxed since macro steps run actions themselves
+            Action::Macro(id) => Box::pin(run_macro_action(app_handle, id)).await,
+        }
+    }
+
+    // Runs an action `steps` times as one job. Volume and seek move by all steps in a single API call, so only the
+    // final target is sent to Spotify, and negative steps go the other way. Track skips are sent back to back
+    pub async fn execute_repeated(self, app_handle: &AppHandle, steps: i32) -> Result<AuthResult, String> {
+        let state = app_handle.state();
+        match self {
+            Action::VolumeUp => change_volume(state, steps).await,
+            Action::VolumeDown => change_volume(state, -steps).await,
+            Action::SeekForward => seek_relative(state, steps).await,
+            Action::SeekBackward => seek_relative(state, -steps).await,
+            Action::NextTrack | Action::PrevTrack => {
+                let mut result = self.execute(app_handle).await;
+                for _ in 1..steps {
+                    if !matches!(result, Ok(AuthResult::Success { .. })) {
+                        break;
+                    }
+                    result = self.execute(app_handle).await;
+                }
+                result
+            }
+            _ => self.execute(app_handle).await,
+        }
+    }
+}
+
+impl Serialize for Action {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Action {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Action::from_name(&name).ok_or_else(|| de::Error::custom(format!("unknown action '{}'", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    // No wildcard arm, so a new variant fails to compile here until it is added to ALL as well
+    fn registry_index(action: Action) -> Option<usize> {
+        match action {
+            Action::PlayPause => Some(0),
+            Action::NextTrack => Some(1),
+            Action::PrevTrack => Some(2),
+            Action::VolumeUp => Some(3),
+            Action::VolumeDown => Some(4),
+            Action::SeekForward => Some(5),
+            Action::SeekBackward => Some(6),
+            Action::LikeTrack => Some(7),
+            Action::CycleProfile => Some(8),
+            Action::ToggleSuspend => Some(9),
+            Action::Macro(_) => None,
+        }
+    }
+
+    #[test]
+    fn all_lists_every_built_in_action_once() {
+        for (index, action) in Action::ALL.into_iter().enumerate() {
+            assert_eq!(registry_index(action), Some(index), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for action in Action::ALL.into_iter().chain([Action::Macro(0), Action::Macro(42)]) {
+            assert_eq!(Action::from_name(&action.name()), Some(action));
+        }
+        let names: HashSet<String> = Action::ALL.into_iter().map(Action::name).collect();
+        assert_eq!(names.len(), Action::ALL.len());
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        for name in ["", "PlayPause", "play pause", "macro:", "macro:x", "macro:-1"] {
+            assert_eq!(Action::from_name(name), None, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn serde_uses_the_name() {
+        let actions = vec![Action::VolumeUp, Action::Macro(3)];
+        let json = serde_json::to_string(&actions).unwrap();
+        assert_eq!(json, r#"["volume_up","macro:3"]"#);
+        assert_eq!(serde_json::from_str::<Vec<Action>>(&json).unwrap(), actions);
+        assert!(serde_json::from_str::<Action>(r#""rewind""#).is_err());
+
+        // Also used as map keys in the hotkey cache
+        let map = HashMap::from([(Action::LikeTrack, 1)]);
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(json, r#"{"like_track":1}"#);
+        assert_eq!(serde_json::from_str::<HashMap<Action, i32>>(&json).unwrap(), map);
+    }
+}
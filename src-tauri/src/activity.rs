@@ -0,0 +1,342 @@
+use serde::{Deserialize, Serialize};
+use once_cell::sync::OnceCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+use tauri::{AppHandle, Manager, State};
+
+use crate::accelerator::{format_hotkey, supported_hotkeys, Trigger};
+use crate::action::Action;
+use crate::api::AuthResult;
+use crate::persist::{quarantine_corrupt, write_atomic};
+use crate::{AppState, APP_CACHE_DIR, USAGE_STATS_FILE};
+
+// How many hotkey events get_hotkey_activity can look back on
+const MAX_ACTIVITY_ENTRIES: usize = 100;
+// Usage stats are written this long after the first unsaved change, and once more when the app exits
+const USAGE_STATS_FLUSH_DELAY: Duration = Duration::from_secs(10);
+
+// What became of a hotkey event, so "my hotkey does nothing" can be told apart from a failing Spotify call
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ActivityOutcome {
+    // Waiting in the action queue or still running
+    Pending,
+    Succeeded,
+    Failed { message: String },
+    // Never executed, e.g. it went stale in the queue
+    Dropped { reason: String },
+    // The OS delivered the hotkey but nothing is bound to it for this press pattern
+    NotBound,
+    ChordArmed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ActivityEntry {
+    pub id: u64,
+    // Unix time in milliseconds when the event was handled
+    pub at_ms: u64,
+    pub hotkey: String,
+    pub trigger: Trigger,
+    pub action: Option<Action>,
+    // From handling the event until the outcome was known
+    pub latency_ms: Option<u64>,
+    pub outcome: ActivityOutcome,
+    #[serde(skip)]
+    started: Option<Instant>,
+}
+
+// Ring buffer of the most recent hotkey events, oldest first
+#[derive(Debug, Default)]
+pub struct ActivityLog {
+    entries: VecDeque<ActivityEntry>,
+    next_id: u64,
+}
+
+impl ActivityLog {
+    // Adds a pending entry and returns its id for the later calls
+    pub fn start(&mut self, hotkey: String, trigger: Trigger, now: Instant, at_ms: u64) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        if self.entries.len() == MAX_ACTIVITY_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(ActivityEntry {
+            id,
+            at_ms,
+            hotkey,
+            trigger,
+            action: None,
+            latency_ms: None,
+            outcome: ActivityOutcome::Pending,
+            started: Some(now),
+        });
+        id
+    }
+
+    pub fn resolve(&mut self, id: u64, action: Action) {
+        if let Some(entry) = self.entry_mut(id) {
+            entry.action = Some(action);
+        }
+    }
+
+    // Returns the finished entry, or None when it already fell out of the buffer
+    pub fn finish(&mut self, id: u64, outcome: ActivityOutcome, now: Instant) -> Option<&ActivityEntry> {
+        let entry = self.entry_mut(id)?;
+        entry.latency_ms = entry
+            .started
+            .map(|started| u64::try_from(now.duration_since(started).as_millis()).unwrap_or(u64::MAX));
+        entry.outcome = outcome;
+        Some(entry)
+    }
+
+    pub fn entries(&self) -> Vec<ActivityEntry> {
+        self.entries.iter().cloned().collect()
+    }
+
+    fn entry_mut(&mut self, id: u64) -> Option<&mut ActivityEntry> {
+        // Ids are handed out in order, so the entry is found from the back in the common case
+        self.entries.iter_mut().rev().find(|entry| entry.id == id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ActionUsage {
+    pub succeeded: u64,
+    pub failed: u64,
+    pub dropped: u64,
+    // Unix time in milliseconds of the last hotkey event for this action
+    pub last_used_ms: Option<u64>,
+}
+
+// Per action counters of hotkey events, kept in memory and flushed to USAGE_STATS_FILE now and then
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct UsageStats {
+    pub actions: BTreeMap<Action, ActionUsage>,
+    // Set by record until the counters are written
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl UsageStats {
+    // Counts a finished action at `at_ms`, outcomes that are not final are skipped
+    pub fn record(&mut self, action: Action, outcome: &ActivityOutcome, at_ms: u64) {
+        if matches!(outcome, ActivityOutcome::Pending | ActivityOutcome::NotBound | ActivityOutcome::ChordArmed) {
+            return;
+        }
+        let usage = self.actions.entry(action).or_default();
+        match outcome {
+            ActivityOutcome::Succeeded => usage.succeeded += 1,
+            ActivityOutcome::Failed { .. } => usage.failed += 1,
+            _ => usage.dropped += 1,
+        }
+        usage.last_used_ms = Some(at_ms);
+        self.dirty = true;
+    }
+
+    // A corrupt file is moved aside, counters are not worth bothering the user about
+    fn load(path: PathBuf) -> Self {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                log::error!("Usage_Stats: Failed to read usage stats: {}", e);
+                return Self::default();
+            }
+        };
+        serde_json::from_str(&contents).unwrap_or_else(|e| {
+            log::error!("Usage_Stats: Usage stats are corrupt, starting over: {}", e);
+            if let Err(e) = quarantine_corrupt(&path) {
+                log::error!("Usage_Stats: Failed to quarantine corrupt usage stats: {}", e);
+            }
+            Self::default()
+        })
+    }
+
+    fn save(&self, path: PathBuf) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        write_atomic(&path, json.as_bytes()).map_err(|e| e.to_string())
+    }
+}
+
+fn usage_stats_path() -> PathBuf {
+    APP_CACHE_DIR
+        .get()
+        .expect("activity: APP_CACHE_DIR not initialized")
+        .join(USAGE_STATS_FILE)
+}
+
+fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX))
+}
+
+// Canonical name of the hotkey behind an event id, ids of combos we never register are shown as is
+fn describe_hotkey_id(hotkey_id: u32) -> String {
+    static HOTKEY_NAMES: OnceCell<HashMap<u32, String>> = OnceCell::new();
+    HOTKEY_NAMES
+        .get_or_init(|| supported_hotkeys().iter().map(|hotkey| (hotkey.id(), format_hotkey(hotkey))).collect())
+        .get(&hotkey_id)
+        .cloned()
+        .unwrap_or_else(|| format!("Unknown hotkey #{}", hotkey_id))
+}
+
+// Logs a hotkey event as it reaches the listener, returns the id to resolve and finish it with
+pub async fn start_activity(app_handle: &AppHandle, hotkey_id: u32, trigger: Trigger) -> u64 {
+    let hotkey = describe_hotkey_id(hotkey_id);
+    let state = app_handle.state::<AppState>();
+    let mut activity_log = state.activity_log.lock().await;
+    activity_log.start(hotkey, trigger, Instant::now(), unix_time_ms())
+}
+
+pub async fn resolve_activity(app_handle: &AppHandle, activity: u64, action: Action) {
+    app_handle.state::<AppState>().activity_log.lock().await.resolve(activity, action);
+}
+
+// Sets the outcome of an event and counts it against `action` in the usage stats. The count does not depend on the
+// activity log, whose entry may already have fallen out of the buffer during a burst of events
+pub async fn finish_activity(app_handle: &AppHandle, activity: u64, action: Option<Action>, outcome: ActivityOutcome) {
+    let state = app_handle.state::<AppState>();
+    if let Some(action) = action {
+        let mut usage_stats = state.usage_stats.lock().await;
+        let flush_scheduled = usage_stats.dirty;
+        usage_stats.record(action, &outcome, unix_time_ms());
+        if usage_stats.dirty && !flush_scheduled {
+            schedule_usage_stats_flush(app_handle.clone());
+        }
+    }
+
+    state.activity_log.lock().await.finish(activity, outcome, Instant::now());
+}
+
+// Reads the saved counters, called once during setup
+pub fn init_usage_stats(app_handle: &AppHandle) {
+    let usage_stats = UsageStats::load(usage_stats_path());
+    tauri::async_runtime::block_on(async {
+        *app_handle.state::<AppState>().usage_stats.lock().await = usage_stats;
+    });
+}
+
+// Writes unsaved counters before the app exits
+pub fn flush_usage_stats(app_handle: &AppHandle) {
+    if let Some(usage_stats) = tauri::async_runtime::block_on(take_unsaved_usage_stats(app_handle))
+        && let Err(e) = usage_stats.save(usage_stats_path())
+    {
+        log::error!("Flush_Usage_Stats: Failed to save usage stats: {}", e);
+    }
+}
+
+// Copy of the counters to write, None when everything is saved already
+async fn take_unsaved_usage_stats(app_handle: &AppHandle) -> Option<UsageStats> {
+    let state = app_handle.state::<AppState>();
+    let mut usage_stats = state.usage_stats.lock().await;
+    if !usage_stats.dirty {
+        return None;
+    }
+    usage_stats.dirty = false;
+    Some(usage_stats.clone())
+}
+
+// Saving fsyncs the file, so it runs on the blocking pool instead of the listener or action worker
+async fn save_usage_stats(usage_stats: UsageStats) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || usage_stats.save(usage_stats_path()))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn schedule_usage_stats_flush(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(USAGE_STATS_FLUSH_DELAY).await;
+        if let Some(usage_stats) = take_unsaved_usage_stats(&app_handle).await
+            && let Err(e) = save_usage_stats(usage_stats).await
+        {
+            log::error!("Flush_Usage_Stats: Failed to save usage stats: {}", e);
+        }
+    });
+}
+
+// Outcome of a finished action, logging failures like the rest of the hotkey path does
+pub fn action_outcome(action: Action, result: Result<AuthResult, String>) -> ActivityOutcome {
+    let message = match result {
+        Ok(AuthResult::Success { .. }) => return ActivityOutcome::Succeeded,
+        Ok(AuthResult::Error { message }) | Err(message) => message,
+        Ok(AuthResult::NeedsAuth { .. }) => "Spotify login required".to_string(),
+    };
+    log::error!("Error in {} action: {}", action.label(), message);
+    ActivityOutcome::Failed { message }
+}
+
+#[tauri::command]
+pub async fn get_hotkey_activity(state: State<'_, AppState>) -> Result<Vec<ActivityEntry>, String> {
+    Ok(state.activity_log.lock().await.entries())
+}
+
+#[tauri::command]
+pub async fn get_usage_stats(state: State<'_, AppState>) -> Result<BTreeMap<Action, ActionUsage>, String> {
+    Ok(state.usage_stats.lock().await.actions.clone())
+}
+
+#[tauri::command]
+pub async fn reset_usage_stats(state: State<'_, AppState>) -> Result<(), String> {
+    *state.usage_stats.lock().await = UsageStats::default();
+    save_usage_stats(UsageStats::default()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn finish_sets_latency_and_outcome() {
+        let start = Instant::now();
+        let mut log = ActivityLog::default();
+        let id = log.start("CTRL + P".to_string(), Trigger::Tap, start, 0);
+        log.resolve(id, Action::PlayPause);
+
+        let entry = log.finish(id, ActivityOutcome::Succeeded, start + Duration::from_millis(250)).unwrap();
+        assert_eq!(entry.action, Some(Action::PlayPause));
+        assert_eq!(entry.latency_ms, Some(250));
+        assert_eq!(entry.outcome, ActivityOutcome::Succeeded);
+    }
+
+    #[test]
+    fn oldest_entries_fall_out_of_the_buffer() {
+        let now = Instant::now();
+        let mut log = ActivityLog::default();
+        let first = log.start("F13".to_string(), Trigger::Tap, now, 0);
+        for _ in 0..MAX_ACTIVITY_ENTRIES {
+            log.start("F14".to_string(), Trigger::Tap, now, 0);
+        }
+
+        let entries = log.entries();
+        assert_eq!(entries.len(), MAX_ACTIVITY_ENTRIES);
+        assert!(entries.iter().all(|entry| entry.hotkey == "F14"));
+        assert!(log.finish(first, ActivityOutcome::Succeeded, now).is_none());
+    }
+
+    #[test]
+    fn usage_only_counts_finished_actions() {
+        let mut stats = UsageStats::default();
+        stats.record(Action::PlayPause, &ActivityOutcome::Pending, 1);
+        // Nothing to write yet
+        assert!(!stats.dirty);
+        for (at_ms, outcome) in [
+            (2, ActivityOutcome::Succeeded),
+            (3, ActivityOutcome::Failed { message: "offline".to_string() }),
+            (4, ActivityOutcome::Succeeded),
+            (5, ActivityOutcome::Dropped { reason: "stale".to_string() }),
+        ] {
+            stats.record(Action::NextTrack, &outcome, at_ms);
+        }
+
+        assert!(stats.dirty);
+        assert_eq!(stats.actions.len(), 1);
+        assert_eq!(
+            stats.actions[&Action::NextTrack],
+            ActionUsage { succeeded: 2, failed: 1, dropped: 1, last_used_ms: Some(5) }
+        );
+    }
+}
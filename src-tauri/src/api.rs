@@ -1,9 +1,13 @@
-use rspotify::{prelude::*, scopes, AuthCodeSpotify, Config, Credentials, OAuth};
+use rspotify::{
+    model::{CurrentlyPlayingContext, PlayableItem},
+    prelude::*, scopes, AuthCodePkceSpotify, Config, Credentials, OAuth, Token,
+};
 use serde::Serialize;
 use std::{
-    env::temp_dir, io::{BufRead, BufReader, Write}, net::TcpListener, sync::Once, thread
+    env::temp_dir, fs, io::{BufRead, BufReader, Write}, net::TcpListener, path::Path, sync::Mutex, thread
 };
-use tauri::{AppHandle, Emitter, State};
+use rand::{distributions::Alphanumeric, Rng};
+use tauri::{AppHandle, Emitter, Manager, State};
 use urlencoding::decode;
 
 #[cfg(target_os = "windows")]
@@ -16,26 +20,31 @@ fn makeword(low: u8, high: u8) -> WORD {
     ((high as WORD) << 8) | (low as WORD)
 }
 
+use crate::settings::{settings_path, Settings, SpotifySettings};
 use crate::AppState;
-static CALLBACK_SERVER: Once = Once::new(); // Only need to run the callback server once
-
-#[derive(Serialize, Clone)]
-struct SpotifyAuthPayload {
-    code: String,
-}
+// Port of the running callback server, which keeps serving for the lifetime of the app
+static CALLBACK_SERVER_PORT: Mutex<Option<u16>> = Mutex::new(None);
+#[cfg(target_os = "windows")]
+static WSA_STARTUP: std::sync::Once = std::sync::Once::new();
 
+// Built-in Spotify app, used unless the settings name another one. PKCE flow, so only the public client id ships
 const CLIENT_ID: &str = "919cdcc0a45d420d80f372105f5b96a0";
-const CLIENT_SECRET: &str = "5f5aeaf0488a4e179f3f764c8f7a3b98";
-const SPOTIFY_TOKEN_CACHE: &str = ".spotify_token.json";
+const SPOTIFY_TOKEN_CACHE: &str = ".spotify_pkce_token.json";
+// Token cache of versions that still used a client secret, moved to SPOTIFY_TOKEN_CACHE on startup
+const LEGACY_SPOTIFY_TOKEN_CACHE: &str = ".spotify_token.json";
+// Volume percent per volume up/down step
+const VOLUME_STEP: i32 = 10;
+// Milliseconds per seek forward/backward step
+const SEEK_STEP_MS: i64 = 10_000;
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub enum AuthResult {
     Success { ok: String },
     NeedsAuth { url: String },
     Error { message: String },
 }
 
-pub fn init_spotify() -> AuthCodeSpotify {
+pub fn init_spotify(settings: &SpotifySettings) -> AuthCodePkceSpotify {
     
     log::info!("Init_Spotify: Initiliazing spotify oauth object");
     
@@ -45,6 +54,7 @@ pub fn init_spotify() -> AuthCodeSpotify {
         cache_path:  temp_dir().join(SPOTIFY_TOKEN_CACHE),
         ..Default::default()
     };
+    migrate_legacy_token_cache(&temp_dir().join(LEGACY_SPOTIFY_TOKEN_CACHE), &config.cache_path);
     
     // TODO - Try to put it in the same spot as the Hotkeys cache
     // issue- appstate.default() runs before we set APP_CACHE_DIR so this init_spotify method errors out
@@ -55,94 +65,182 @@ pub fn init_spotify() -> AuthCodeSpotify {
         "user-read-private",
         "user-read-recently-played",
         "user-library-read",
+        "user-library-modify",
         "user-read-currently-playing",
         "user-read-playback-state",
         "user-read-playback-position",
         "user-modify-playback-state"
     );
 
-    let creds = Credentials::new(CLIENT_ID, CLIENT_SECRET);
+    let creds = Credentials::new_pkce(settings.client_id.as_deref().unwrap_or(CLIENT_ID));
 
     let oauth = OAuth {
         scopes: api_scopes,
-        redirect_uri: "http://localhost:8888/callback".to_owned(),
+        redirect_uri: settings.redirect_uri().to_owned(),
         ..Default::default()
     };
 
-    AuthCodeSpotify::with_config(creds, oauth, config)
+    AuthCodePkceSpotify::with_config(creds, oauth, config)
+}
+
+// Keeps users logged in across the switch to PKCE. The old access token works until it expires, after that its
+// refresh token may be refused since it was issued to the client secret flow, and init_auth starts a new login
+fn migrate_legacy_token_cache(legacy_path: &Path, cache_path: &Path) {
+    if !legacy_path.exists() {
+        return;
+    }
+    if cache_path.exists() {
+        log::info!("Init_Spotify: Removing legacy token cache, already migrated");
+    } else {
+        match Token::from_cache(legacy_path).and_then(|token| token.write_cache(cache_path)) {
+            Ok(()) => log::info!("Init_Spotify: Migrated legacy token cache"),
+            Err(e) => log::warn!("Init_Spotify: Dropping unreadable legacy token cache: {}", e),
+        }
+    }
+    if let Err(e) = fs::remove_file(legacy_path) {
+        log::error!("Init_Spotify: Failed to remove legacy token cache: {}", e);
+    }
 }
 
-fn start_callback_server(app_handle: AppHandle) {
-    CALLBACK_SERVER.call_once(move || {
-        #[cfg(target_os = "windows")]
-        {
-            // Initialize WSA
-            let _wsa_data = unsafe {
-                let mut data = std::mem::zeroed();
-                WSAStartup(makeword(2, 2), &mut data);
-                data
-            };
+// Serves the OAuth callback on the first of `ports` that is free and returns the port in use.
+// A server that already runs on one of `ports` is reused, one on a port that was removed from the settings
+// keeps running until the app restarts but is no longer handed to Spotify
+fn start_callback_server(app_handle: AppHandle, ports: &[u16]) -> Result<u16, String> {
+    let mut running_port = CALLBACK_SERVER_PORT.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(port) = *running_port
+        && ports.contains(&port)
+    {
+        return Ok(port);
+    }
+
+    #[cfg(target_os = "windows")]
+    WSA_STARTUP.call_once(|| {
+        // Initialize WSA
+        let _wsa_data = unsafe {
+            let mut data = std::mem::zeroed();
+            WSAStartup(makeword(2, 2), &mut data);
+            data
+        };
+    });
+
+    let mut bind_errors = Vec::new();
+    let Some((listener, port)) = ports.iter().find_map(|port| match TcpListener::bind(("127.0.0.1", *port)) {
+        Ok(listener) => Some((listener, *port)),
+        Err(e) => {
+            log::warn!("Callback_server: Could not listen on port {}: {}", port, e);
+            bind_errors.push(format!("{} ({})", port, e));
+            None
         }
-        let thread_app_handle = app_handle.clone();
-        thread::spawn(move || {
-            let app_handle = thread_app_handle;
-            let listener = TcpListener::bind("127.0.0.1:8888").unwrap();
-            log::info!("Callback_server: listening on port 8888");
-
-            for stream in listener.incoming() {
-                match stream {
-                    Ok(mut stream) => {
-                        // Read the request to get the URL with code
-                        let buf_reader = BufReader::new(&stream);
-                        let request_line = buf_reader.lines().next();
-
-                        if let Some(Ok(line)) = request_line {
-                            log::debug!("Callback_server: Received callback request");
-
-                            if let Some(code) = extract_code_from_request_line(&line) {
-                                log::debug!("Callback_server: Extracted auth code");
-                                if let Err(e) = app_handle.emit(
-                                    "spotify-auth-code",
-                                    SpotifyAuthPayload { code: code.clone() },
-                                ) {
-                                    log::error!("Callback_server: Failed to emit auth code: {}", e);
-                                } else {
-                                    log::debug!("Callback_server: Emitted auth code event to frontend");
-                                }
-                            } else {
-                                log::warn!("Callback_server: Unable to parse auth code from request line: {}", line);
-                            }
-
-                            let response = format!("HTTP/1.1 200 OK\r\n\
-                                Content-Type: text/html\r\n\
-                                Access-Control-Allow-Origin: *\r\n\
-                                \r\n\
-                                <html><body><script>\
-                                console.log('Callback page loaded');\
-                                const urlParams = new URLSearchParams(window.location.search);\
-                                const code = urlParams.get('code');\
-                                if (window.opener && code) {{\
-                                    console.log('Sending code to opener:', code);\
-                                    window.opener.postMessage({{ type: 'spotify-callback', code: code }}, '*');\
-                                    window.close();\
-                                }}\
-                                </script>\
-                                <p>Authentication successful! You can close this window.</p>\
-                                </body></html>");
-
-                            stream.write_all(response.as_bytes()).unwrap();
+    }) else {
+        return Err(format!(
+            "Could not start the login callback server, every configured port is unavailable: {}",
+            bind_errors.join(", ")
+        ));
+    };
+    log::info!("Callback_server: listening on port {}", port);
+    *running_port = Some(port);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(mut stream) => {
+                    // Read the request to get the URL with code
+                    let buf_reader = BufReader::new(&stream);
+                    let request_line = buf_reader.lines().next();
+
+                    if let Some(Ok(line)) = request_line {
+                        log::debug!("Callback_server: Received callback request");
+                        let response = handle_callback_request(&app_handle, &line);
+                        if let Err(e) = stream.write_all(response.as_bytes()) {
+                            log::error!("Callback_server: Failed to send callback page: {}", e);
                         }
                     }
-                    Err(e) => {
-                        log::error!("Callback_server: Error: {}", e);
-                    }
+                }
+                Err(e) => {
+                    log::error!("Callback_server: Error: {}", e);
                 }
             }
-        });
+        }
     });
+    Ok(port)
+}
+
+// Query parameters Spotify redirects back with
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+// Checks the callback against the login started by init_auth and exchanges the code, returns the HTTP response.
+// Only a request carrying the state of the pending login is accepted, until a code was exchanged with it, so another
+// page or app cannot slip its own code in. Nothing from the request is echoed back into the page
+fn handle_callback_request(app_handle: &AppHandle, request_line: &str) -> String {
+    let Some(params) = parse_callback_request(request_line) else {
+        log::warn!("Callback_server: Ignoring request that is not a login callback");
+        return callback_page("404 Not Found", "Not found.");
+    };
+
+    let state = app_handle.state::<AppState>();
+    // Cleared by exchange_auth_code once a code went through, so a replayed callback is rejected from then on
+    if !is_pending_state(&state.pending_auth_state.blocking_lock(), &params.state) {
+        log::warn!("Callback_server: Rejecting callback with a missing or unexpected state");
+        return callback_page(
+            "400 Bad Request",
+            "This login link is not the one the app is waiting for. Please start the login again from the app.",
+        );
+    }
+
+    if let Some(error) = params.error {
+        log::error!("Callback_server: Spotify returned an error: {}", error);
+        let message = if error == "access_denied" {
+            "Spotify access was declined. You can close this window and try again from the app."
+        } else {
+            "Spotify could not complete the login. You can close this window and try again from the app."
+        };
+        return callback_page("400 Bad Request", message);
+    }
+    let Some(code) = params.code else {
+        log::warn!("Callback_server: Callback without an auth code");
+        return callback_page("400 Bad Request", "Spotify did not send a login code. Please try again from the app.");
+    };
+
+    // Exchanged right here so the login completes even while the app window is hidden
+    log::debug!("Callback_server: Extracted auth code, requesting token");
+    match tauri::async_runtime::block_on(exchange_auth_code(app_handle, &state, &code)) {
+        AuthResult::Success { .. } => callback_page("200 OK", "Authentication successful! You can close this window."),
+        _ => callback_page(
+            "500 Internal Server Error",
+            "Spotify accepted the login but the app could not get a token. Please try again from the app.",
+        ),
+    }
+}
+
+// A callback or pasted redirect belongs to the pending login only when it carries exactly its state
+fn is_pending_state(pending: &Option<String>, received: &Option<String>) -> bool {
+    pending.is_some() && pending == received
 }
 
-fn extract_code_from_request_line(request_line: &str) -> Option<String> {
+fn callback_page(status: &str, message: &str) -> String {
+    let body = format!("<html><body><p>{}</p></body></html>", message);
+    format!(
+        "HTTP/1.1 {}\r\n\
+        Content-Type: text/html; charset=utf-8\r\n\
+        Content-Length: {}\r\n\
+        Cache-Control: no-store\r\n\
+        Content-Security-Policy: default-src 'none'\r\n\
+        Connection: close\r\n\
+        \r\n\
+        {}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// None unless the request is a GET to /callback
+fn parse_callback_request(request_line: &str) -> Option<CallbackParams> {
     let mut parts = request_line.split_whitespace();
     let method = parts.next()?;
     if method != "GET" {
@@ -150,29 +248,32 @@ fn extract_code_from_request_line(request_line: &str) -> Option<String> {
     }
 
     let path_and_query = parts.next()?;
-    let (path, query) = path_and_query.split_once('?')?;
-    if !path.starts_with("/callback") {
+    let (path, query) = path_and_query.split_once('?').unwrap_or((path_and_query, ""));
+    if path != "/callback" {
         return None;
     }
 
-    for kv in query.split('&') {
-        let (key, value) = kv.split_once('=')?;
-        if key == "code" {
-            return decode(value).ok().map(|c| c.into_owned());
+    let mut params = CallbackParams::default();
+    for (key, value) in query.split('&').filter_map(|kv| kv.split_once('=')) {
+        let Ok(value) = decode(value).map(|value| value.into_owned()) else {
+            continue;
+        };
+        match key {
+            "code" => params.code = Some(value),
+            "state" => params.state = Some(value),
+            "error" => params.error = Some(value),
+            _ => {}
         }
     }
-
-    None
+    Some(params)
 }
 
 #[tauri::command]
 pub async fn init_auth(app_handle: tauri::AppHandle, state: State<'_, AppState>) -> Result<AuthResult, String> {
-    start_callback_server(app_handle);
-
     log::debug!("Init_Auth: Called");
 
-    let spotify_lock = state.spotify.lock().await;
-    let spotify = spotify_lock.as_ref().unwrap();
+    let mut spotify_lock = state.spotify.lock().await;
+    let spotify = spotify_lock.as_mut().unwrap();
     // Check for existing token
     if let Ok(Some(token)) = spotify.read_token_cache(true).await {
         
@@ -183,7 +284,8 @@ pub async fn init_auth(app_handle: tauri::AppHandle, state: State<'_, AppState>)
         if token.is_expired() {
             log::debug!("Init_Auth: Token expired, attempting refresh");
             match spotify.refresh_token().await {
-                Ok(()) => {
+                // A token without a refresh token "refreshes" into no token at all
+                Ok(()) if spotify.get_token().lock().await.unwrap().is_some() => {
                     log::debug!("Init_Auth: Token refreshed successfully");
                     return Ok(AuthResult::Success {
                         ok: "ok".to_string(),
@@ -202,37 +304,91 @@ pub async fn init_auth(app_handle: tauri::AppHandle, state: State<'_, AppState>)
         }
     }
 
-    // No valid token, start new auth flow
-    let url = spotify.get_authorize_url(true).unwrap();
+    // No valid token, start new auth flow. Spotify has to redirect to the port the callback server got
+    let settings = Settings::load(&settings_path()).spotify;
+    let port = match start_callback_server(app_handle, &settings.callback_ports()) {
+        Ok(port) => port,
+        Err(message) => {
+            log::error!("Init_Auth: {}", message);
+            return Ok(AuthResult::Error { message });
+        }
+    };
+    spotify.oauth.redirect_uri = settings.redirect_uri_for_port(port);
+    // Fresh state for every login, the callback server only accepts the latest one
+    let auth_state: String = rand::thread_rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect();
+    spotify.oauth.state = auth_state.clone();
+    *state.pending_auth_state.lock().await = Some(auth_state);
+
+    // This also creates the code verifier handle_callback needs
+    let url = spotify.get_authorize_url(None).unwrap();
 
     Ok(AuthResult::NeedsAuth {
         url: url.to_string(),
     })
 }
 
+// Manual fallback for when the callback server could not finish the login. Takes the whole redirect URL pasted by
+// the user, the callback server normally exchanges the code itself. A bare code is refused since only the URL
+// carries the state that ties it to the login started by init_auth
 #[tauri::command]
 pub async fn handle_callback(
+    app_handle: AppHandle,
     state: State<'_, AppState>,
     code: String,
 ) -> Result<AuthResult, String> {
     
     log::debug!("Handle_callback: Received code from frontend vue!");
+    let url = tauri::Url::parse(code.trim())
+        .map_err(|_| "Please paste the whole address the browser ended up on, not just the code".to_string())?;
+    let param = |name: &str| url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+
+    // Same check as the callback server
+    if !is_pending_state(&*state.pending_auth_state.lock().await, &param("state")) {
+        log::warn!("Handle_callback: Rejecting pasted redirect with a missing or unexpected state");
+        return Err("This login link is not the one the app is waiting for. Please start the login again.".to_string());
+    }
+    let code = param("code").ok_or_else(|| "The pasted URL does not contain a login code".to_string())?;
+    Ok(exchange_auth_code(&app_handle, &state, &code).await)
+}
 
+// Trades the auth code for a token on the shared client and tells the frontend through "auth-state-changed"
+pub async fn exchange_auth_code(app_handle: &AppHandle, state: &AppState, code: &str) -> AuthResult {
+    let result = request_auth_token(state, code).await;
+    // Only a successful exchange ends the login, after a failed one the redirect can still be pasted by hand
+    if let AuthResult::Success { .. } = result {
+        state.pending_auth_state.lock().await.take();
+    }
+    if let Err(e) = app_handle.emit("auth-state-changed", result.clone()) {
+        log::error!("Exchange_Auth_Code: Failed to emit auth state: {}", e);
+    }
+    result
+}
+
+async fn request_auth_token(state: &AppState, code: &str) -> AuthResult {
     let mut spotify_lock = state.spotify.lock().await;
-    let spotify = spotify_lock
-        .as_mut()
-        .ok_or_else(|| "Handle_callback: Spotify client not initialized".to_string())?;
+    let Some(spotify) = spotify_lock.as_mut() else {
+        return AuthResult::Error {
+            message: "Exchange_Auth_Code: Spotify client not initialized".to_string(),
+        };
+    };
 
-    match spotify.request_token(&code).await {
+    // request_token panics without the verifier from init_auth, e.g. for a code from before a restart
+    if spotify.verifier.is_none() {
+        return AuthResult::Error {
+            message: "Exchange_Auth_Code: No login in progress, please log in again".to_string(),
+        };
+    }
+
+    match spotify.request_token(code).await {
         Ok(_) => {
-            log::debug!("Handle_callback: Successfully requested token");
+            log::debug!("Exchange_Auth_Code: Successfully requested token");
             // Successfully got token, try to cache it
             if let Some(token) = spotify.get_token().lock().await.unwrap().clone() {
                 
-                log::debug!("Handle_callback: Attempting to cache token to: {:?}", spotify.config.cache_path);
+                log::debug!("Exchange_Auth_Code: Attempting to cache token to: {:?}", spotify.config.cache_path);
                 match token.write_cache(&spotify.config.cache_path) {
-                    Ok(_) => log::debug!("Handle_callback: Successfully cached token"),
-                    Err(e) => log::error!("Handle_callback: Failed to cache token: {}", e),
+                    Ok(_) => log::debug!("Exchange_Auth_Code: Successfully cached token"),
+                    Err(e) => log::error!("Exchange_Auth_Code: Failed to cache token: {}", e),
                 }
             }
 
@@ -241,25 +397,25 @@ pub async fn handle_callback(
                 Ok(Some(playback)) => {
                     let mut volume_lock = state.volume.lock().await;
                     *volume_lock = playback.device.volume_percent.unwrap_or(50) as u8;
-                    log::debug!("Handle_callback: Current playback state volume: {:?}", *volume_lock);
+                    log::debug!("Exchange_Auth_Code: Current playback state volume: {:?}", *volume_lock);
                 }
                 Ok(None) => {
-                    log::debug!("Handle_callback: No active playback");
+                    log::debug!("Exchange_Auth_Code: No active playback");
                 }
                 Err(e) => {
-                    log::error!("Handle_callback: Failed to get playback state: {}", e);
+                    log::error!("Exchange_Auth_Code: Failed to get playback state: {}", e);
                 }
             }
 
-            Ok(AuthResult::Success {
+            AuthResult::Success {
                 ok: "ok".to_string(),
-            })
+            }
         }
         Err(e) => {
-            log::error!("Handle_callback: Token request failed with error: {:?}", e);
-            Ok(AuthResult::Error {
-                message: format!("Handle_callback: Failed to request token: {}", e),
-            })
+            log::error!("Exchange_Auth_Code: Token request failed with error: {:?}", e);
+            AuthResult::Error {
+                message: format!("Exchange_Auth_Code: Failed to request token: {}", e),
+            }
         }
     }
 }
@@ -375,45 +531,163 @@ pub async fn prev_track(state: State<'_, AppState>) -> Result<AuthResult, String
 #[tauri::command]
 pub async fn volume_control_up(state: State<'_, AppState>) -> Result<AuthResult, String> {
     log::debug!("Volume_Control_Up: Called");
+    change_volume(state, 1).await
+}
+
+#[tauri::command]
+pub async fn volume_control_down(state: State<'_, AppState>) -> Result<AuthResult, String> {
+    log::debug!("Volume_Control_Down: Called");
+    change_volume(state, -1).await
+}
+
+// Moves the volume by `steps` times VOLUME_STEP in a single API call, so a held volume key sends only its final target
+pub async fn change_volume(state: State<'_, AppState>, steps: i32) -> Result<AuthResult, String> {
     let spotify = state.spotify.lock().await;
     let mut volume_lock = state.volume.lock().await;
     if let Some(spotify) = &*spotify {
-        log::info!("Volume_Control_Up: Current volume: {:?} | Setting to: {:?}", *volume_lock, (*volume_lock + 10).min(100));
-        *volume_lock = (*volume_lock + 10).min(100); // Increase volume by 10, max 100
+        let target = (*volume_lock as i32).saturating_add(steps.saturating_mul(VOLUME_STEP)).clamp(0, 100) as u8;
+        log::info!("Change_Volume: Current volume: {:?} | Setting to: {:?}", *volume_lock, target);
+        *volume_lock = target;
         match spotify.volume(*volume_lock, None).await {
             Ok(_) => Ok(AuthResult::Success {
                 ok: "ok".to_string(),
             }),
             Err(e) => Ok(AuthResult::Error {
-                message: format!("Volume_Control_Up: API failed: {}", e),
+                message: format!("Change_Volume: API failed: {}", e),
             }),
         }
     } else {
         Ok(AuthResult::Error {
-            message: "Volume_Control_Up: No active playback".to_string(),
+            message: "Change_Volume: No active playback".to_string(),
         })
     }
 }
 
 #[tauri::command]
-pub async fn volume_control_down(state: State<'_, AppState>) -> Result<AuthResult, String> {
-    log::debug!("Volume_Control_Down: Called");
+pub async fn seek_forward(state: State<'_, AppState>) -> Result<AuthResult, String> {
+    log::debug!("Seek_Forward: Called");
+    seek_relative(state, 1).await
+}
+
+#[tauri::command]
+pub async fn seek_backward(state: State<'_, AppState>) -> Result<AuthResult, String> {
+    log::debug!("Seek_Backward: Called");
+    seek_relative(state, -1).await
+}
+
+// Seeks `steps` times SEEK_STEP_MS from the current position in a single API call, clamped to the current item
+pub async fn seek_relative(state: State<'_, AppState>, steps: i32) -> Result<AuthResult, String> {
     let spotify = state.spotify.lock().await;
-    let mut volume_lock = state.volume.lock().await;
     if let Some(spotify) = &*spotify {
-        log::info!("Volume_Control_Down: Current volume: {:?} | Setting to: {:?}", *volume_lock, (*volume_lock as i8 - 10).max(0) as u8);  
-        *volume_lock = (*volume_lock as i8 - 10).max(0) as u8; // Decrease volume by 10, min 0
-        match spotify.volume(*volume_lock, None).await {
+        match spotify.current_playback(None, None::<Vec<_>>).await {
+            Ok(Some(playback)) => {
+                let progress = playback.progress.unwrap_or_default();
+                let mut target = progress + chrono::Duration::milliseconds(steps as i64 * SEEK_STEP_MS);
+                match &playback.item {
+                    Some(PlayableItem::Track(track)) => target = target.min(track.duration),
+                    Some(PlayableItem::Episode(episode)) => target = target.min(episode.duration),
+                    None => {}
+                }
+                target = target.max(chrono::Duration::zero());
+                log::info!(
+                    "Seek_Relative: Current position: {}ms | Seeking to: {}ms",
+                    progress.num_milliseconds(),
+                    target.num_milliseconds()
+                );
+
+                match spotify.seek_track(target, None).await {
+                    Ok(_) => Ok(AuthResult::Success {
+                        ok: "ok".to_string(),
+                    }),
+                    Err(e) => Ok(AuthResult::Error {
+                        message: format!("Seek_Relative: API failed: {}", e),
+                    }),
+                }
+            }
+            Ok(None) => Ok(AuthResult::Error {
+                message: "Seek_Relative: No active playback".to_string(),
+            }),
+            Err(e) => Ok(AuthResult::Error {
+                message: format!("Seek_Relative: Failed to get playback state: {}", e),
+            }),
+        }
+    } else {
+        Ok(AuthResult::Error {
+            message: "Seek_Relative: Spotify client not initialized".to_string(),
+        })
+    }
+}
+
+// Saves the currently playing track to the user's Liked Songs
+#[tauri::command]
+pub async fn like_track(state: State<'_, AppState>) -> Result<AuthResult, String> {
+    log::debug!("Like_Track: Called");
+    let spotify = state.spotify.lock().await;
+    if let Some(spotify) = &*spotify {
+        let track_id = match spotify.current_playing(None, None::<Vec<_>>).await {
+            Ok(Some(CurrentlyPlayingContext { item: Some(PlayableItem::Track(track)), .. })) => track.id,
+            Ok(_) => None,
+            Err(e) => {
+                return Ok(AuthResult::Error {
+                    message: format!("Like_Track: Failed to get currently playing track: {}", e),
+                })
+            }
+        };
+        let Some(track_id) = track_id else {
+            return Ok(AuthResult::Error {
+                message: "Like_Track: No track is playing".to_string(),
+            });
+        };
+
+        match spotify.current_user_saved_tracks_add([track_id]).await {
             Ok(_) => Ok(AuthResult::Success {
                 ok: "ok".to_string(),
             }),
             Err(e) => Ok(AuthResult::Error {
-                message: format!("Volume_Control_Down: API failed: {}", e),
+                message: format!("Like_Track: API failed: {}", e),
             }),
         }
     } else {
         Ok(AuthResult::Error {
-            message: "Volume_Control_Down: No active playback".to_string(),
+            message: "Like_Track: Spotify client not initialized".to_string(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callback_params_are_decoded() {
+        let params = parse_callback_request("GET /callback?code=abc%2Ddef&state=xyz HTTP/1.1").unwrap();
+        assert_eq!(
+            params,
+            CallbackParams { code: Some("abc-def".to_string()), state: Some("xyz".to_string()), error: None }
+        );
+
+        let params = parse_callback_request("GET /callback?error=access_denied&state=xyz HTTP/1.1").unwrap();
+        assert_eq!(params.error.as_deref(), Some("access_denied"));
+        assert_eq!(params.code, None);
+    }
+
+    #[test]
+    fn other_requests_are_not_callbacks() {
+        assert_eq!(parse_callback_request("POST /callback?code=abc HTTP/1.1"), None);
+        assert_eq!(parse_callback_request("GET /callbackevil?code=abc HTTP/1.1"), None);
+        assert_eq!(parse_callback_request("GET /favicon.ico HTTP/1.1"), None);
+        assert_eq!(parse_callback_request("GET /callback HTTP/1.1"), Some(CallbackParams::default()));
+    }
+
+    #[test]
+    fn only_the_pending_state_is_accepted() {
+        let pending = Some("xyz".to_string());
+        assert!(is_pending_state(&pending, &Some("xyz".to_string())));
+        assert!(!is_pending_state(&pending, &Some("abc".to_string())));
+        // A link with the state stripped off
+        assert!(!is_pending_state(&pending, &None));
+        // No login in progress, or it already went through
+        assert!(!is_pending_state(&None, &None));
+        assert!(!is_pending_state(&None, &Some("xyz".to_string())));
+    }
+}
@@ -1,93 +1,194 @@
-use global_hotkey::{
-    hotkey::{Code, HotKey, Modifiers},
-    GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState,
-};
-use crossbeam_channel::TryRecvError;
-use serde::{Deserialize, Serialize};
-use std::time::Duration;
-use std::{cell::RefCell, collections::HashMap, fs, path::{Path, PathBuf}};
-use tauri::{Manager, State};
-
-use crate::{AppState, APP_CACHE_DIR};
-use crate::HOTKEY_CACHE;
-use crate::{api::AuthResult, next_track, play_pause, prev_track, volume_control_up, volume_control_down};
+use global_hotkey::{hotkey::HotKey, GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use std::{cell::RefCell, collections::{BTreeMap, HashMap, HashSet}, path::PathBuf, sync::Mutex};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tauri::{Emitter, Manager, State};
+use tauri_plugin_dialog::{DialogExt, FilePath};
+
+use crate::accelerator::{format_binding, parse_binding, supported_key_names, Binding, HotkeyParseError, Trigger};
+use crate::action::Action;
+use crate::activity::{action_outcome, finish_activity, resolve_activity, start_activity, ActivityOutcome};
+use crate::hotkey_cache::{CacheLoadError, HotkeyCache, HotkeyLock, HotkeyProfileFile};
+use crate::macros::{merge_imported_macros, Macro};
+use crate::persist::quarantine_corrupt;
+use crate::press::{BoundTriggers, KeyRepeater, PressDetector, PressThresholds};
+use crate::queue::enqueue_action;
+use crate::recorder::capture_recorded_hotkey;
+use crate::{AppState, SuspendMenuItem};
 
 // Hotkey manager needs to be declared in the same thread as the registration of hotkeys
 // So reinforcing that fact that making it thread_local and registering only in the main thread
 thread_local! {
-    pub static HOTKEY_MANAGER: RefCell<Option<GlobalHotKeyManager>> = RefCell::new(None);
+    pub static HOTKEY_MANAGER: RefCell<Option<GlobalHotKeyManager>> = const { RefCell::new(None) };
+    // Hotkeys currently registered through HOTKEY_MANAGER, only touched on the main thread as well
+    static REGISTERED_HOTKEYS: RefCell<HashSet<HotKey>> = RefCell::new(HashSet::new());
 }
 
-// Persist Hotkeys
-#[derive(Serialize, Deserialize, Debug)]
-struct HotkeyCache {
-    string_hotkeys: HashMap<String, String>,
-}
+// How long an armed chord waits for its follow-up key
+const CHORD_TIMEOUT: Duration = Duration::from_millis(1500);
+
+// Sender used by the global-hotkey event handler, taken on shutdown so the listener task ends
+static HOTKEY_EVENT_SENDER: Mutex<Option<UnboundedSender<GlobalHotKeyEvent>>> = Mutex::new(None);
 
-impl HotkeyCache {
-    fn save_to_file(&self, path: PathBuf) -> Result<(), String> {
-        let data = serde_json::to_string(self).map_err(|e| e.to_string())?;
-        fs::write(path, data).map_err(|e| e.to_string())?;
+// Function to save reversed hotkeys into the active profile, always written in canonical form.
+// Passing a profile makes it the active one first, in the same write
+pub fn save_hotkeys_to_cache(
+    hotkey_lock: &HotkeyLock<'_>,
+    hotkeys: &HashMap<Action, Vec<Binding>>,
+    profile: Option<&str>,
+) -> Result<(), String> {
+    let string_hotkeys = hotkeys
+        .iter()
+        .map(|(action, bindings)| (*action, bindings.iter().map(format_binding).collect()))
+        .collect();
+    HotkeyCache::update(hotkey_lock, |cache| {
+        if let Some(profile) = profile {
+            cache.active_profile = profile.to_string();
+        }
+        cache.set_active_bindings(string_hotkeys);
         Ok(())
-    }
+    })
+}
 
-    fn load_from_file(path: PathBuf) -> Result<Self, String> {
-        let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
-        serde_json::from_str(&data).map_err(|e| e.to_string())
+// Everything read from the hotkey cache on app boot
+#[derive(Default)]
+pub struct LoadedHotkeys {
+    pub hotkeys: HashMap<Action, Vec<Binding>>,
+    // Bindings that fail to parse or conflict, reported to the frontend instead of being registered
+    pub invalid: Vec<BindingResult>,
+    // Set when the cache could not be read at all, so the user knows why their hotkeys are gone
+    pub warning: Option<String>,
+    pub press_thresholds: PressThresholds,
+}
+
+// Function to load reversed hotkeys on app boot.
+// A corrupt cache is moved aside as `.corrupt` and reported instead of silently resetting to no hotkeys
+pub fn load_hotkeys_from_cache(cache_file_path: PathBuf) -> LoadedHotkeys {
+    let cache = match HotkeyCache::load_from_file(cache_file_path.clone()) {
+        Ok(cache) => cache,
+        Err(CacheLoadError::Missing) => return LoadedHotkeys::default(),
+        Err(CacheLoadError::Corrupt(e)) => {
+            log::error!("LOAD_HOTKEY_FROM_CACHE: Hotkey cache is corrupt: {}", e);
+            let warning = match quarantine_corrupt(&cache_file_path) {
+                Ok(corrupt_path) => format!(
+                    "Your saved hotkeys could not be read and were reset. The unreadable file was kept at {:?}",
+                    corrupt_path
+                ),
+                Err(quarantine_err) => {
+                    log::error!("LOAD_HOTKEY_FROM_CACHE: Failed to quarantine corrupt cache: {}", quarantine_err);
+                    "Your saved hotkeys could not be read and were reset".to_string()
+                }
+            };
+            return LoadedHotkeys { warning: Some(warning), ..Default::default() };
+        }
+        Err(e) => {
+            log::error!("LOAD_HOTKEY_FROM_CACHE: Failed to load hotkey cache: {}", e);
+            return LoadedHotkeys {
+                warning: Some(format!("Your saved hotkeys could not be loaded: {}", e)),
+                ..Default::default()
+            };
+        }
+    };
+
+    log::info!("LOAD_HOTKEY_FROM_CACHE: Loading hotkeys of profile '{}'", cache.active_profile);
+    let (hotkeys, results) = validate_bindings(cache.active_bindings(), &cache.macros);
+    let invalid: Vec<BindingResult> = results.into_iter().filter(|result| result.error.is_some()).collect();
+    for result in &invalid {
+        log::error!(
+            "LOAD_HOTKEY_FROM_CACHE: Skipping hotkey '{}' for {:?}: {}",
+            result.accelerator,
+            result.action,
+            result.message.as_deref().unwrap_or_default()
+        );
     }
+
+    LoadedHotkeys { hotkeys, invalid, warning: None, press_thresholds: cache.press_thresholds }
 }
 
-// Function to save reversed hotkeys
-pub fn save_hotkeys_to_cache(
-    string_hotkeys: HashMap<String, String>,
-    cache_file_path: PathBuf,
-) -> Result<(), String> {
-    let cache = HotkeyCache { string_hotkeys };
-    cache.save_to_file(cache_file_path)
-}
-
-// Function to load reversed hotkeys on app boot
-pub fn load_hotkeys_from_cache(cache_file_path: PathBuf) -> HashMap<String, HotKey> {
-    if let Ok(cache) = HotkeyCache::load_from_file(cache_file_path) {
-        let mut hotkey_map: HashMap<String, HotKey> = HashMap::new();
-        let string_hotkeys = cache.string_hotkeys;
-        for (name, hotkey_str) in string_hotkeys {
-            match parse_hotkey(&hotkey_str) {
-                Ok(hotkey) => {
-                    hotkey_map.insert(name, hotkey);
+// Parses every non-empty accelerator and checks that no binding is bound to two different actions.
+// A plain hotkey that also leads a chord conflicts as well, since pressing it would be ambiguous, and macros have to
+// exist in `macros`. Returns the usable bindings per action and one result per accelerator, conflicting ones are
+// failed on every side
+pub fn validate_bindings(
+    bindings: HashMap<Action, Vec<String>>,
+    macros: &BTreeMap<u32, Macro>,
+) -> (HashMap<Action, Vec<Binding>>, Vec<BindingResult>) {
+    let mut sorted_bindings: Vec<(Action, Vec<String>)> = bindings.into_iter().collect();
+    sorted_bindings.sort_by_key(|(action, _)| *action);
+
+    let mut parsed: Vec<(BindingResult, Option<Binding>)> = Vec::new();
+    let mut owners: HashMap<Binding, Vec<Action>> = HashMap::new();
+    let mut single_owners: HashMap<HotKey, Vec<Action>> = HashMap::new();
+    let mut leader_owners: HashMap<HotKey, Vec<Action>> = HashMap::new();
+
+    for (action, accelerators) in sorted_bindings {
+        let accelerators = accelerators.into_iter().map(|a| a.trim().to_string()).filter(|a| !a.is_empty());
+        for accelerator in accelerators {
+            // E.g. an imported layout pointing at a macro of another machine
+            if let Action::Macro(id) = action
+                && !macros.contains_key(&id)
+            {
+                parsed.push((BindingResult::failed(action, accelerator, BindingError::UnknownMacro(id)), None));
+                continue;
+            }
+            match parse_binding(&accelerator) {
+                Ok(binding) => {
+                    let by_hotkey = if binding.then.is_some() { &mut leader_owners } else { &mut single_owners };
+                    for binding_owners in [owners.entry(binding).or_default(), by_hotkey.entry(binding.hotkey).or_default()] {
+                        if !binding_owners.contains(&action) {
+                            binding_owners.push(action);
+                        }
+                    }
+                    parsed.push((BindingResult::ok(action, accelerator), Some(binding)));
                 }
-                Err(e) => {
-                    log::error!("LOAD_HOTKEY_FROM_CACHE: Failed to parse hotkey '{}': {}", hotkey_str, e);
+                Err(e) => parsed.push((BindingResult::failed(action, accelerator, BindingError::Invalid(e)), None)),
+            }
+        }
+    }
+
+    let mut hotkeys: HashMap<Action, Vec<Binding>> = HashMap::new();
+    let mut results = Vec::new();
+    for (mut result, binding) in parsed {
+        if let Some(binding) = binding {
+            let mut others: Vec<Action> = owners[&binding].iter().copied().filter(|other| *other != result.action).collect();
+            let shadowing = if binding.then.is_some() { &single_owners } else { &leader_owners };
+            for other in shadowing.get(&binding.hotkey).into_iter().flatten() {
+                if !others.contains(other) {
+                    others.push(*other);
                 }
             }
+
+            if others.is_empty() {
+                let action_bindings = hotkeys.entry(result.action).or_default();
+                if !action_bindings.contains(&binding) {
+                    action_bindings.push(binding);
+                }
+            } else {
+                result.fail(BindingError::Conflict(others));
+            }
         }
-        hotkey_map
-    } else {
-        HashMap::new() // Return an empty map if loading fails
+        results.push(result);
     }
+
+    (hotkeys, results)
 }
 
-pub fn ensure_hotkey_cache_file_exists(cache_dir: &Path) -> Result<(), String> {
-    let cache_path = cache_dir.join(HOTKEY_CACHE);
+pub fn ensure_hotkey_cache_file_exists() -> Result<(), String> {
+    let cache_path = HotkeyCache::path();
     if cache_path.exists() {
         return Ok(());
     }
 
-    let cache = HotkeyCache {
-        string_hotkeys: HashMap::new(),
-    };
+    let cache = HotkeyCache::default();
 
     cache.save_to_file(cache_path)
 }
 
 #[tauri::command]
 pub async fn return_loaded_hotkeys(
-) -> Result<HashMap<String, String>, String> {
-    let cache_path = APP_CACHE_DIR
-        .get()
-        .expect("hotkey: APP_CACHE_DIR not initialized")
-        .join(HOTKEY_CACHE);
-
+) -> Result<HashMap<Action, Vec<String>>, String> {
+    let cache_path = HotkeyCache::path();
     if !cache_path.exists() {
         log::warn!(
             "Return_Loaded_Hotkeys: Hotkey cache missing at {:?}, returning defaults",
@@ -96,7 +197,16 @@ pub async fn return_loaded_hotkeys(
         return Ok(HashMap::new());
     }
 
-    HotkeyCache::load_from_file(cache_path).map(|cache| cache.string_hotkeys).map_err(|e| {
+    // Return the canonical form, bindings that no longer parse are passed through untouched so the UI can show them
+    let canonicalize = |hotkey_str: String| parse_binding(&hotkey_str).map(|binding| format_binding(&binding)).unwrap_or(hotkey_str);
+
+    HotkeyCache::load_from_file(cache_path).map(|cache| {
+        cache
+            .active_bindings()
+            .into_iter()
+            .map(|(action, hotkey_strs)| (action, hotkey_strs.into_iter().map(canonicalize).collect()))
+            .collect()
+    }).map_err(|e| {
         log::error!("Return_Loaded_Hotkeys: Failed to load cache: {}", e);
         format!("Failed to load hotkeys: {}", e)
     })
@@ -106,134 +216,319 @@ pub async fn return_loaded_hotkeys(
 pub async fn set_hotkeys(
     app_handle: tauri::AppHandle,
     state: State<'_, AppState>,
-    play_pause_hotkey: String,
-    next_track_hotkey: String,
-    prev_track_hotkey: String,
-    volume_up_hotkey: String,
-    volume_down_hotkey: String,
-) -> Result<AuthResult, String> {
-
-    // TODO - need to check if they are empty and skip otherwise
-    
-    let mut save_hotkeys = HashMap::new();
-    save_hotkeys.insert("play_pause".to_string(), play_pause_hotkey.clone());
-    save_hotkeys.insert("next_track".to_string(), next_track_hotkey.clone());
-    save_hotkeys.insert("prev_track".to_string(), prev_track_hotkey.clone());
-    save_hotkeys.insert("volume_up".to_string(), volume_up_hotkey.clone());
-    save_hotkeys.insert("volume_down".to_string(), volume_down_hotkey.clone());
-
-    let _ = save_hotkeys_to_cache(save_hotkeys, APP_CACHE_DIR.get().expect("hotkey: APP_CACHE_DIR not initialized").join(HOTKEY_CACHE));
-    
-    let new_hotkeys: HashMap<String, HotKey> =
-        get_hotkeys(play_pause_hotkey, next_track_hotkey, prev_track_hotkey, volume_up_hotkey, volume_down_hotkey)?;
-    let cloned_new_hotkeys = new_hotkeys.clone();
-
-    // Lock the hotkey_hashmap and extract data before entering the main thread logic
-    let mut hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    hotkeys: HashMap<Action, String>,
+) -> Result<Vec<BindingResult>, String> {
+    let bindings = hotkeys
+        .into_iter()
+        .map(|(action, hotkey_str)| (action, vec![hotkey_str]))
+        .collect();
 
-    // Get a mutable reference to the hashmap inside the Mutex
-    if let Some(hotkey_map) = hotkey_map_guard.as_mut() {
-        // Prepare to unregister the existing hotkeys
-        let old_hotkeys: Vec<(String, HotKey)> = hotkey_map.drain().collect();
-
-        let _ = app_handle.run_on_main_thread(move || {
-            HOTKEY_MANAGER.with(|manager| {
-                if let Some(hotkey_manager) = manager.borrow().as_ref() {
-                    // Unregister old hotkeys
-                    for (name, hotkey) in old_hotkeys {
-                        if let Err(e) = hotkey_manager.unregister(hotkey) {
-                            log::error!("Set_Hotkeys: Failed to unregister hotkey '{}': {}", name, e);
-                        } else {
-                            log::debug!("Set_Hotkeys: Unregistered hotkey '{}'", name);
-                        }
-                    }
+    apply_bindings(&app_handle, &state, bindings, None).await
+}
 
-                    // Register new hotkeys
-                    for (name, hotkey) in cloned_new_hotkeys {
-                        if let Err(e) = hotkey_manager.register(hotkey) {
-                            log::error!("Set_Hotkeys: Failed to register hotkey '{}': {}", name, e);
-                        } else {
-                            log::debug!("Set_Hotkeys: Registered hotkey '{}', {:?}", name, hotkey);
-                        }
-                    }
-                } else {
-                    log::error!("Set_Hotkeys: HOTKEY_MANAGER is not initialized!");
-                }
-            });
-        });
+// Warning from loading the hotkey cache on startup, e.g. when a corrupt cache had to be reset
+#[tauri::command]
+pub async fn get_hotkey_cache_warning(state: State<'_, AppState>) -> Result<Option<String>, String> {
+    Ok(state.hotkey_cache_warning.lock().await.clone())
+}
 
-        // Update the hashmap with the new hotkeys
-        hotkey_map.extend(new_hotkeys);
+// Canonical names of every key that can be used in a hotkey, so the UI does not keep its own list
+#[tauri::command]
+pub fn get_supported_keys() -> Vec<String> {
+    supported_key_names().into_iter().map(String::from).collect()
+}
+
+// Bindings from the last load or save that are invalid or could not be registered
+#[tauri::command]
+pub async fn get_binding_errors(state: State<'_, AppState>) -> Result<Vec<BindingResult>, String> {
+    Ok(state.binding_errors.lock().await.clone())
+}
+
+// Asks the user where to export to or what to import from, None when the dialog was cancelled
+async fn pick_profile_file(app_handle: &tauri::AppHandle, save: bool) -> Result<Option<PathBuf>, String> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let dialog = app_handle.dialog().file().add_filter("Hotkey profile", &["json", "toml"]);
+    let on_picked = move |path: Option<FilePath>| {
+        let _ = tx.send(path);
+    };
+    if save {
+        dialog.set_file_name("hotkeys.json").save_file(on_picked);
     } else {
-        log::error!("Set_Hotkeys: Hotkey hashmap is not initialized!");
+        dialog.pick_file(on_picked);
     }
 
-    Ok(AuthResult::Success {
-        ok: "ok".to_string(),
-    })
+    let Some(path) = rx.await.map_err(|e| e.to_string())? else {
+        return Ok(None);
+    };
+    path.into_path().map(Some).map_err(|e| e.to_string())
 }
 
-pub fn get_hotkeys(
-    play_pause_hotkey: String,
-    next_track_hotkey: String,
-    prev_track_hotkey: String,
-    volume_up_hotkey: String,
-    volume_down_hotkey: String,
-) -> Result<HashMap<String, HotKey>, String> {
-    let mut hotkeys = HashMap::new();
+// Writes the saved bindings and the macros they use to a file picked by the user, TOML if it ends in .toml and
+// JSON otherwise. Returns false when the dialog was cancelled
+#[tauri::command]
+pub async fn export_hotkeys(app_handle: tauri::AppHandle) -> Result<bool, String> {
+    let Some(path) = pick_profile_file(&app_handle, true).await? else {
+        return Ok(false);
+    };
+
+    let cache = match HotkeyCache::load_from_file(HotkeyCache::path()) {
+        Ok(cache) => cache,
+        Err(CacheLoadError::Missing) => HotkeyCache::default(),
+        Err(e) => return Err(format!("Failed to load hotkeys: {}", e)),
+    };
+    let bindings = cache.active_bindings();
+    let macros = cache
+        .macros
+        .into_iter()
+        .filter(|(id, _)| bindings.get(&Action::Macro(*id)).is_some_and(|hotkeys| !hotkeys.is_empty()))
+        .collect();
 
-    if !play_pause_hotkey.is_empty() {
-        if let Ok(hotkey) = parse_hotkey(&play_pause_hotkey) {
-            hotkeys.insert("play_pause".to_string(), hotkey);
-        }
+    log::info!("Export_Hotkeys: Exporting {} actions to {:?}", bindings.len(), path);
+    HotkeyProfileFile::new(bindings, macros)
+        .save_to_file(&path)
+        .map_err(|e| format!("Failed to export hotkeys: {}", e))?;
+    Ok(true)
+}
+
+// Reads a file picked by the user that was written by export_hotkeys and applies it like set_bindings does,
+// nothing is changed if any entry is invalid or conflicts with another one. Macros in the file are added under
+// free ids and their bindings follow them. Returns None when the dialog was cancelled
+#[tauri::command]
+pub async fn import_hotkeys(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Option<Vec<BindingResult>>, String> {
+    let Some(path) = pick_profile_file(&app_handle, false).await? else {
+        return Ok(None);
+    };
+    let profile = HotkeyProfileFile::load_from_file(&path).map_err(|e| format!("Failed to import hotkeys: {}", e))?;
+    log::info!("Import_Hotkeys: Importing {} actions from {:?}", profile.bindings.len(), path);
+
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    let mut macros = HotkeyCache::load_or_default(HotkeyCache::path())?.macros;
+    let macro_ids = merge_imported_macros(&mut macros, profile.macros)?;
+
+    // Macro bindings without their macro in the file, e.g. from a v1 export, would point at whatever macro has
+    // that id here, so they are reported instead
+    let mut missing_macros = Vec::new();
+    let mut bindings: HashMap<Action, Vec<String>> = HashMap::new();
+    for (action, accelerators) in profile.bindings {
+        let action = match action {
+            Action::Macro(id) => match macro_ids.get(&id) {
+                Some(local_id) => Action::Macro(*local_id),
+                None => {
+                    let accelerators = accelerators.into_iter().filter(|a| !a.trim().is_empty());
+                    missing_macros.extend(accelerators.map(|a| BindingResult::failed(action, a, BindingError::UnknownMacro(id))));
+                    continue;
+                }
+            },
+            _ => action,
+        };
+        bindings.insert(action, accelerators);
     }
 
-    if !next_track_hotkey.is_empty() {
-        if let Ok(hotkey) = parse_hotkey(&next_track_hotkey) {
-            hotkeys.insert("next_track".to_string(), hotkey);
-        }
+    // The macros are only kept when the bindings are going to be applied
+    let (_, mut results) = validate_bindings(bindings.clone(), &macros);
+    if !missing_macros.is_empty() || results.iter().any(|result| result.error.is_some()) {
+        log::warn!("Import_Hotkeys: Validation failed, nothing was imported");
+        results.extend(missing_macros);
+        return Ok(Some(results));
     }
+    HotkeyCache::update(&hotkey_map_guard, |cache| {
+        cache.macros = macros;
+        Ok(())
+    })?;
+    drop(hotkey_map_guard);
 
-    if !prev_track_hotkey.is_empty() {
-        if let Ok(hotkey) = parse_hotkey(&prev_track_hotkey) {
-            hotkeys.insert("prev_track".to_string(), hotkey);
-        }
+    apply_bindings(&app_handle, &state, bindings, None).await.map(Some)
+}
+
+// Outcome of a single accelerator passed to set_bindings
+#[derive(Serialize, Clone, Debug)]
+pub struct BindingResult {
+    pub action: Action,
+    pub accelerator: String,
+    pub error: Option<BindingError>,
+    pub message: Option<String>,
+}
+
+impl BindingResult {
+    fn ok(action: Action, accelerator: String) -> Self {
+        Self { action, accelerator, error: None, message: None }
     }
 
-    if !volume_up_hotkey.is_empty() {
-        if let Ok(hotkey) = parse_hotkey(&volume_up_hotkey) {
-            hotkeys.insert("volume_up".to_string(), hotkey);
-        }
+    fn failed(action: Action, accelerator: String, error: BindingError) -> Self {
+        let mut result = Self::ok(action, accelerator);
+        result.fail(error);
+        result
+    }
+
+    fn fail(&mut self, error: BindingError) {
+        self.message = Some(error.to_string());
+        self.error = Some(error);
     }
+}
 
-    if !volume_down_hotkey.is_empty() {
-        if let Ok(hotkey) = parse_hotkey(&volume_down_hotkey) {
-            hotkeys.insert("volume_down".to_string(), hotkey);
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+pub enum BindingError {
+    Invalid(HotkeyParseError),
+    // The same hotkey is also bound to these other actions
+    Conflict(Vec<Action>),
+    UnknownMacro(u32),
+    RegistrationFailed(String),
+}
+
+impl std::fmt::Display for BindingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindingError::Invalid(e) => write!(f, "invalid hotkey: {}", e),
+            BindingError::Conflict(others) => {
+                let labels: Vec<&str> = others.iter().map(|action| action.label()).collect();
+                write!(f, "already used by {}", labels.join(", "))
+            }
+            BindingError::UnknownMacro(id) => write!(f, "macro {} does not exist", id),
+            BindingError::RegistrationFailed(message) => write!(f, "failed to register: {}", message),
         }
     }
+}
 
-    Ok(hotkeys)
+#[tauri::command]
+pub async fn set_bindings(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    bindings: HashMap<Action, Vec<String>>,
+) -> Result<Vec<BindingResult>, String> {
+    apply_bindings(&app_handle, &state, bindings, None).await
 }
 
-fn parse_hotkey(hotkey_str: &str) -> Result<HotKey, String> {
-    let parts: Vec<&str> = hotkey_str.split(" + ").map(|s| s.trim()).collect();
-    let mut modifiers = Modifiers::empty();
-    let mut key = String::new();
+// Validates every binding and, only if all of them parse without conflicts, swaps the registered set in one go.
+// The bindings are saved to `profile` when given, which then becomes the active profile, otherwise to the active one.
+// Returns one result per non-empty accelerator so the frontend can show which ones failed
+pub async fn apply_bindings(
+    app_handle: &tauri::AppHandle,
+    state: &State<'_, AppState>,
+    bindings: HashMap<Action, Vec<String>>,
+    profile: Option<&str>,
+) -> Result<Vec<BindingResult>, String> {
+    // Hold the lock across validation and registration so concurrent calls cannot interleave their swaps,
+    // and a macro cannot be deleted between checking it exists and binding it
+    let mut hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    let macros = match HotkeyCache::load_or_default(HotkeyCache::path()) {
+        Ok(cache) => cache.macros,
+        Err(e) => {
+            log::error!("Apply_Bindings: Failed to load macros from cache: {}", e);
+            BTreeMap::new()
+        }
+    };
+    let (mut new_hotkeys, mut results) = validate_bindings(bindings, &macros);
+
+    if results.iter().any(|result| result.error.is_some()) {
+        log::warn!("Apply_Bindings: Validation failed, keeping the currently registered hotkeys");
+        return Ok(results);
+    }
+
+    if hotkey_map_guard.is_none() {
+        log::error!("Apply_Bindings: Hotkey hashmap is not initialized!");
+        return Err("Hotkey hashmap is not initialized".to_string());
+    }
+
+    let suspended = *state.hotkeys_suspended.lock().await;
+    let failures = sync_registered_hotkeys(app_handle, desired_hotkeys(&new_hotkeys, suspended)).await?;
 
-    for part in parts {
-        match part.to_uppercase().as_str() {
-            "CTRL" | "CONTROL" => modifiers |= Modifiers::CONTROL,
-            "ALT" => modifiers |= Modifiers::ALT,
-            "SHIFT" => modifiers |= Modifiers::SHIFT,
-            "META" | "COMMAND" => modifiers |= Modifiers::META,
-            k => key = k.to_string(),
+    // Saved with the bindings that failed to register as well, so they are tried again on the next start
+    if let Err(e) = save_hotkeys_to_cache(&hotkey_map_guard, &new_hotkeys, profile) {
+        log::error!("Apply_Bindings: Failed to save hotkeys to cache: {}", e);
+    }
+    for result in results.iter_mut() {
+        if let Some(e) = parse_binding(&result.accelerator).ok().and_then(|binding| failures.get(&binding.hotkey)) {
+            log::error!("Apply_Bindings: Failed to register '{}' for {:?}: {}", result.accelerator, result.action, e);
+            result.fail(BindingError::RegistrationFailed(e.clone()));
         }
     }
+    for bindings in new_hotkeys.values_mut() {
+        bindings.retain(|binding| !failures.contains_key(&binding.hotkey));
+    }
+
+    *hotkey_map_guard = Some(new_hotkeys);
+    drop(hotkey_map_guard);
+
+    *state.binding_errors.lock().await = results.iter().filter(|result| result.error.is_some()).cloned().collect();
+
+    Ok(results)
+}
 
-    let code = <Code as CodeExt>::from_str(&key.to_uppercase()).unwrap();
-    let hotkey = HotKey::new(Some(modifiers), code);
-    //hotkey.id = rand::random::<u32>();
-    Ok(hotkey)
+// Hotkeys that should be registered with the OS. While suspended only the suspend toggle itself stays registered.
+// Chords only register their leader here, the follow-up keys are added while the chord is armed
+pub fn desired_hotkeys(hotkey_map: &HashMap<Action, Vec<Binding>>, suspended: bool) -> HashSet<HotKey> {
+    hotkey_map
+        .iter()
+        .filter(|(action, _)| !suspended || action.is_suspend_exempt())
+        .flat_map(|(_, bindings)| bindings.iter().map(|binding| binding.hotkey))
+        .collect()
+}
+
+// Makes the set of OS-registered hotkeys equal to `desired`, only touching the ones that changed.
+// Runs on the main thread since that is where HOTKEY_MANAGER lives, returns the hotkeys that failed to register
+pub async fn sync_registered_hotkeys(
+    app_handle: &tauri::AppHandle,
+    desired: HashSet<HotKey>,
+) -> Result<HashMap<HotKey, String>, String> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app_handle
+        .run_on_main_thread(move || {
+            let _ = tx.send(sync_registered_hotkeys_on_main_thread(desired));
+        })
+        .map_err(|e| e.to_string())?;
+
+    rx.await.map_err(|e| e.to_string())?
+}
+
+fn sync_registered_hotkeys_on_main_thread(desired: HashSet<HotKey>) -> Result<HashMap<HotKey, String>, String> {
+    HOTKEY_MANAGER.with(|manager| {
+        let manager = manager.borrow();
+        let Some(hotkey_manager) = manager.as_ref() else {
+            log::error!("Sync_Registered_Hotkeys: HOTKEY_MANAGER is not initialized!");
+            return Err("HOTKEY_MANAGER is not initialized".to_string());
+        };
+
+        REGISTERED_HOTKEYS.with(|registered| {
+            let mut registered = registered.borrow_mut();
+
+            // Unregister hotkeys that are no longer wanted
+            let stale: Vec<HotKey> = registered.difference(&desired).copied().collect();
+            for hotkey in stale {
+                if let Err(e) = hotkey_manager.unregister(hotkey) {
+                    log::error!("Sync_Registered_Hotkeys: Failed to unregister hotkey {:?}: {}", hotkey, e);
+                } else {
+                    log::debug!("Sync_Registered_Hotkeys: Unregistered hotkey {:?}", hotkey);
+                }
+                registered.remove(&hotkey);
+            }
+
+            // Register the new ones
+            let mut failures = HashMap::new();
+            for hotkey in desired {
+                if registered.contains(&hotkey) {
+                    continue;
+                }
+                match hotkey_manager.register(hotkey) {
+                    Ok(()) => {
+                        log::debug!("Sync_Registered_Hotkeys: Registered hotkey {:?}", hotkey);
+                        registered.insert(hotkey);
+                    }
+                    // Only debug here, some callers expect failures, e.g. the recorder trying every combo.
+                    // The others report the failures that matter to them
+                    Err(e) => {
+                        log::debug!("Sync_Registered_Hotkeys: Failed to register hotkey {:?}: {}", hotkey, e);
+                        failures.insert(hotkey, e.to_string());
+                    }
+                }
+            }
+
+            Ok(failures)
+        })
+    })
 }
 
 pub fn init_hotkeys(app_handle: tauri::AppHandle) {
@@ -244,221 +539,499 @@ pub fn init_hotkeys(app_handle: tauri::AppHandle) {
 
     let loaded_hotkey_app_handle = app_handle.clone();
     // Load hotkeys from cache, if it exists
-    let loaded_hotkeys = load_hotkeys_from_cache(APP_CACHE_DIR.get().expect("hotkey: APP_CACHE_DIR not initialized").join(HOTKEY_CACHE));
-    let cloned_loaded_hotkeys = loaded_hotkeys.clone();
-    if !loaded_hotkeys.is_empty() {
-        // Lock the hotkey_hashmap and extract data before entering the main thread logic
-
-        let _ = app_handle.run_on_main_thread(move || {
-            HOTKEY_MANAGER.with(|manager| {
-                if let Some(hotkey_manager) = manager.borrow().as_ref() {
-                    // Register hotkeys
-                    for (name, hotkey) in loaded_hotkeys {
-                        if let Err(e) = hotkey_manager.register(hotkey) {
-                            log::error!("Init_Hotkeys: Failed to register hotkey '{}': {}", name, e);
-                        } else {
-                            log::debug!("Init_Hotkeys: Registered hotkey '{}', {:?}", name, hotkey);
-                        }
-                    }
-                } else {
-                    log::error!("Init_Hotkeys: HOTKEY_MANAGER is not initialized!");
-                }
-            });
-        });
-
+    let LoadedHotkeys { hotkeys: loaded_hotkeys, invalid: invalid_hotkeys, warning, press_thresholds } =
+        load_hotkeys_from_cache(HotkeyCache::path());
+    if !loaded_hotkeys.is_empty()
+        || !invalid_hotkeys.is_empty()
+        || warning.is_some()
+        || press_thresholds != PressThresholds::default()
+    {
         tauri::async_runtime::spawn(async move {
-            log::debug!("Init_Hotkeys: Adding previously persited hotkeys to Hotkey_map");
+            log::debug!("Init_Hotkeys: Registering previously persisted hotkeys");
             let app_state = loaded_hotkey_app_handle.state::<AppState>();
-            let mut hotkey_map_guard = app_state.hotkey_hashmap.lock().await;
-            if let Some(hotkey_map) = hotkey_map_guard.as_mut() {
-                hotkey_map.extend(cloned_loaded_hotkeys);
+            *app_state.press_thresholds.lock().await = press_thresholds;
+            *app_state.binding_errors.lock().await = invalid_hotkeys;
+            if let Some(warning) = warning {
+                *app_state.hotkey_cache_warning.lock().await = Some(warning.clone());
+                if let Err(e) = loaded_hotkey_app_handle.emit("hotkey-cache-warning", warning) {
+                    log::error!("Init_Hotkeys: Failed to emit hotkey cache warning: {}", e);
+                }
             }
-        });
-    }
-
-    let app_handle_for_hotkey = app_handle.clone();
-    // Start new thread to listen to hotkey events
-    tauri::async_runtime::spawn(async move {
-        log::info!("Init_Hotkeys: Starting hotkey event listener thread");
+            let mut hotkey_map_guard = app_state.hotkey_hashmap.lock().await;
 
-        let global_hotkey_receiver = GlobalHotKeyEvent::receiver();
+            let desired = desired_hotkeys(&loaded_hotkeys, *app_state.hotkeys_suspended.lock().await);
+            match sync_registered_hotkeys(&loaded_hotkey_app_handle, desired).await {
+                Ok(failures) => {
+                    // Re-check the OS side on every start, another app may have taken a combo since it was saved
+                    let mut binding_errors = app_state.binding_errors.lock().await;
+                    for (action, bindings) in &loaded_hotkeys {
+                        for (binding, e) in bindings.iter().filter_map(|binding| failures.get(&binding.hotkey).map(|e| (binding, e))) {
+                            let accelerator = format_binding(binding);
+                            log::error!("Init_Hotkeys: Failed to register '{}' for {:?}: {}", accelerator, action, e);
+                            let error = BindingError::RegistrationFailed(e.clone());
+                            binding_errors.push(BindingResult::failed(*action, accelerator, error));
+                        }
+                    }
 
-        loop {
-            match global_hotkey_receiver.try_recv() {
-                Ok(event) => {
-                    if event.state == HotKeyState::Released {
-                        handle_hotkey_event(app_handle_for_hotkey.state(), event.id).await;
+                    if let Some(hotkey_map) = hotkey_map_guard.as_mut() {
+                        for (action, mut bindings) in loaded_hotkeys {
+                            bindings.retain(|binding| !failures.contains_key(&binding.hotkey));
+                            hotkey_map.insert(action, bindings);
+                        }
                     }
                 }
-                Err(e) => match e {
-                    TryRecvError::Empty => {
-                        // No events, sleep briefly to avoid busy-waiting
-                        tokio::time::sleep(Duration::from_millis(10)).await;
-                    }
-                    TryRecvError::Disconnected => {
-                        log::error!("Init_Hotkeys: Hotkey receiver disconnected");
-                        break;
-                    }
-                },
+                Err(e) => {
+                    log::error!("Init_Hotkeys: Failed to register persisted hotkeys: {}", e);
+                }
             }
+            drop(hotkey_map_guard);
+
+            let binding_errors = app_state.binding_errors.lock().await.clone();
+            if !binding_errors.is_empty()
+                && let Err(e) = loaded_hotkey_app_handle.emit("hotkey-binding-errors", binding_errors)
+            {
+                log::error!("Init_Hotkeys: Failed to emit binding errors: {}", e);
+            }
+        });
+    }
+
+    // Forward events from global-hotkey straight into the runtime, the listener only wakes up for an event or a timer
+    let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel();
+    *HOTKEY_EVENT_SENDER.lock().unwrap_or_else(|e| e.into_inner()) = Some(event_tx);
+    GlobalHotKeyEvent::set_event_handler(Some(|event: GlobalHotKeyEvent| {
+        if let Ok(sender) = HOTKEY_EVENT_SENDER.lock()
+            && let Some(sender) = sender.as_ref()
+        {
+            let _ = sender.send(event);
         }
+    }));
 
-        log::info!("Init_Hotkeys: Hotkey event listener thread ended");
-    });
+    tauri::async_runtime::spawn(listen_for_hotkeys(app_handle, event_rx));
+}
+
+// Stops the hotkey listener by closing its channel, called when the app exits
+pub fn shutdown_hotkey_listener() {
+    if HOTKEY_EVENT_SENDER.lock().unwrap_or_else(|e| e.into_inner()).take().is_some() {
+        log::info!("Shutdown_Hotkey_Listener: Stopping hotkey event listener");
+    }
 }
 
-pub async fn handle_hotkey_event(state: State<'_, AppState>, hotkey_id: u32) {
-    // Awaiting the lock to access HOTKEY_HASHMAP
-    // let hotkey_map = HOTKEY_HASHMAP.lock().await;
+async fn listen_for_hotkeys(app_handle: tauri::AppHandle, mut event_rx: UnboundedReceiver<GlobalHotKeyEvent>) {
+    log::info!("Listen_For_Hotkeys: Starting hotkey event listener");
 
-    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
-    if let Some(hotkey_map) = hotkey_map_guard.as_ref() {
-        // Find the hotkey associated with the triggered event
-        if let Some((name, _)) = hotkey_map
-            .iter()
-            .find(|(_, hotkey)| hotkey.id() == hotkey_id)
-        {
-            // Perform the action associated with this hotkey
-            match name.as_str() {
-                "play_pause" => {
-                    drop(hotkey_map_guard);
-                    if let Err(e) = play_pause(state).await {
-                        log::error!("Error in play/pause action: {}", e);
-                    }
-                }
-                "next_track" => {
-                    drop(hotkey_map_guard);
-                    if let Err(e) = next_track(state).await {
-                        log::error!("Error in play/pause action: {}", e);
-                    }
+    let mut armed_chord: Option<ArmedChord> = None;
+    let mut press_detector = PressDetector::default();
+    let mut key_repeater = KeyRepeater::default();
+
+    loop {
+        // Sleep until the next hold, expired double tap window, repeat of a held key or chord timeout,
+        // or forever when none is pending
+        let deadline = [
+            press_detector.next_deadline(),
+            key_repeater.next_deadline(),
+            armed_chord.as_ref().map(|chord| chord.deadline),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let timer = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        let event = tokio::select! {
+            event = event_rx.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = timer => {
+                for (hotkey_id, trigger) in press_detector.poll(Instant::now()) {
+                    handle_hotkey_event(&app_handle, &mut armed_chord, hotkey_id, trigger).await;
                 }
-                "prev_track" => {
-                    drop(hotkey_map_guard);
-                    if let Err(e) = prev_track(state).await {
-                        log::error!("Error in play/pause action: {}", e);
-                    }
+                // Holds that reached their step limit run now, the others on release
+                for (hotkey_id, action, steps) in key_repeater.poll(Instant::now()) {
+                    let activity = start_activity(&app_handle, hotkey_id, Trigger::Tap).await;
+                    run_repeated_action(&app_handle, action, steps, activity).await;
                 }
-                "volume_up" => {
-                    drop(hotkey_map_guard);
-                    if let Err(e) = volume_control_up(state).await {
-                        log::error!("Error in volume up action: {}", e);
-                    }
+                if armed_chord.as_ref().is_some_and(|chord| Instant::now() >= chord.deadline) {
+                    log::info!("Listen_For_Hotkeys: Chord timed out waiting for a follow-up key");
+                    armed_chord = None;
+                    sync_chord_follow_ups(&app_handle, HashSet::new()).await;
                 }
-                "volume_down" => {
-                    drop(hotkey_map_guard);
-                    if let Err(e) = volume_control_down(state).await {
-                        log::error!("Error in volume down action: {}", e);
+                continue;
+            }
+        };
+
+        if event.state == HotKeyState::Pressed && capture_recorded_hotkey(&app_handle, event.id).await {
+            continue;
+        }
+
+        let now = Instant::now();
+        let trigger = match event.state {
+            // Key repeat from the OS while a repeatable key is held
+            HotKeyState::Pressed if key_repeater.os_repeat(event.id, now) => None,
+            HotKeyState::Pressed => {
+                let thresholds = *app_handle.state::<AppState>().press_thresholds.lock().await;
+                press_detector.thresholds = thresholds;
+                key_repeater.thresholds = thresholds;
+
+                match press_behaviour(&app_handle, armed_chord.as_ref(), event.id).await {
+                    (_, Some(action)) => {
+                        if armed_chord.take().is_some() {
+                            sync_chord_follow_ups(&app_handle, HashSet::new()).await;
+                        }
+                        key_repeater.pressed(event.id, action, now);
+                        None
                     }
+                    (bound, None) => press_detector.pressed(event.id, bound, now),
                 }
-                _ => {
-                    log::error!("Unknown hotkey action for '{}'", name);
+            }
+            // The whole hold runs as one job, so only the final volume or position is sent to Spotify
+            HotKeyState::Released if key_repeater.is_held(event.id) => {
+                if let Some((action, steps)) = key_repeater.released(event.id) {
+                    let activity = start_activity(&app_handle, event.id, Trigger::Tap).await;
+                    run_repeated_action(&app_handle, action, steps, activity).await;
                 }
+                None
             }
-        } else {
-            log::error!("Hotkey ID '{}' not found in HOTKEY_HASHMAP", hotkey_id);
+            HotKeyState::Released => press_detector.released(event.id, now),
+        };
+        if let Some(trigger) = trigger {
+            handle_hotkey_event(&app_handle, &mut armed_chord, event.id, trigger).await;
         }
     }
+
+    log::info!("Listen_For_Hotkeys: Hotkey event listener ended");
 }
 
-//Extension trait to parse Code from string
-trait CodeExt {
-    fn from_str(s: &str) -> Result<Code, String>;
-}
-
-impl CodeExt for Code {
-    fn from_str(s: &str) -> Result<Code, String> {
-        match s {
-            // Digits
-            "0" => Ok(Code::Digit0),
-            "1" => Ok(Code::Digit1),
-            "2" => Ok(Code::Digit2),
-            "3" => Ok(Code::Digit3),
-            "4" => Ok(Code::Digit4),
-            "5" => Ok(Code::Digit5),
-            "6" => Ok(Code::Digit6),
-            "7" => Ok(Code::Digit7),
-            "8" => Ok(Code::Digit8),
-            "9" => Ok(Code::Digit9),
-
-            // Letters
-            "A" => Ok(Code::KeyA),
-            "B" => Ok(Code::KeyB),
-            "C" => Ok(Code::KeyC),
-            "D" => Ok(Code::KeyD),
-            "E" => Ok(Code::KeyE),
-            "F" => Ok(Code::KeyF),
-            "G" => Ok(Code::KeyG),
-            "H" => Ok(Code::KeyH),
-            "I" => Ok(Code::KeyI),
-            "J" => Ok(Code::KeyJ),
-            "K" => Ok(Code::KeyK),
-            "L" => Ok(Code::KeyL),
-            "M" => Ok(Code::KeyM),
-            "N" => Ok(Code::KeyN),
-            "O" => Ok(Code::KeyO),
-            "P" => Ok(Code::KeyP),
-            "Q" => Ok(Code::KeyQ),
-            "R" => Ok(Code::KeyR),
-            "S" => Ok(Code::KeyS),
-            "T" => Ok(Code::KeyT),
-            "U" => Ok(Code::KeyU),
-            "V" => Ok(Code::KeyV),
-            "W" => Ok(Code::KeyW),
-            "X" => Ok(Code::KeyX),
-            "Y" => Ok(Code::KeyY),
-            "Z" => Ok(Code::KeyZ),
-
-            // Special keys
-            "SPACE" => Ok(Code::Space),
-            "ENTER" => Ok(Code::Enter),
-            "-" => Ok(Code::Minus),
-            "=" => Ok(Code::Equal),
-            "/" => Ok(Code::Slash),
-            "\\" => Ok(Code::Backslash),
-            ";" => Ok(Code::Semicolon),
-            "'" => Ok(Code::Quote),
-            "," => Ok(Code::Comma),
-            "." => Ok(Code::Period),
-            "[" => Ok(Code::BracketLeft),
-            "]" => Ok(Code::BracketRight),
-            "`" => Ok(Code::Backquote),
-            "Home" => Ok(Code::Home),
-            "End" => Ok(Code::End),
-            "PageUp" => Ok(Code::PageUp),
-            "PageDown" => Ok(Code::PageDown),
-            "Delete" => Ok(Code::Delete),
-            "Backspace" => Ok(Code::Backspace),
-            "Escape" => Ok(Code::Escape),
-            "Tab" => Ok(Code::Tab),
-            "PrintScreen" => Ok(Code::PrintScreen),
-            "ScrollLock" => Ok(Code::ScrollLock),
-            "Pause" => Ok(Code::Pause),
-            "Insert" => Ok(Code::Insert),
-            "NumLock" => Ok(Code::NumLock),
-            "F1" => Ok(Code::F1),
-            "F2" => Ok(Code::F2),
-            "F3" => Ok(Code::F3),
-            "F4" => Ok(Code::F4),
-            "F5" => Ok(Code::F5),
-            "F6" => Ok(Code::F6),
-            "F7" => Ok(Code::F7),
-            "F8" => Ok(Code::F8),
-            "F9" => Ok(Code::F9),
-            "F10" => Ok(Code::F10),
-            "F11" => Ok(Code::F11),
-            "F12" => Ok(Code::F12),
-            "F13" => Ok(Code::F13),
-            "F14" => Ok(Code::F14),
-            "F15" => Ok(Code::F15),
-            "F16" => Ok(Code::F16),
-            "F17" => Ok(Code::F17),
-            "F18" => Ok(Code::F18),
-            "F19" => Ok(Code::F19),
-            "F20" => Ok(Code::F20),
-            _ => {
-                log::error!("Unsupported key '{}' was pressed. Please create a new issue in Github.", s);
-                Err(format!("The key '{}' is not supported as a hotkey. Please create a new issue in Github.", s))
+// Leader of a chord that was just released, waiting for one of its follow-up keys until `deadline`
+pub struct ArmedChord {
+    follow_ups: HashMap<HotKey, Action>,
+    deadline: Instant,
+}
+
+// Press patterns bound on a hotkey, so the press detector knows whether a tap has to wait, and the repeatable
+// action to repeat while the key is held. Keys that also have a double tap or hold binding never repeat,
+// and follow-up keys of an armed chord are always plain taps
+async fn press_behaviour(
+    app_handle: &tauri::AppHandle,
+    armed_chord: Option<&ArmedChord>,
+    hotkey_id: u32,
+) -> (BoundTriggers, Option<Action>) {
+    let mut bound = BoundTriggers::default();
+    if armed_chord.is_some_and(|chord| chord.follow_ups.keys().any(|hotkey| hotkey.id() == hotkey_id)) {
+        return (bound, None);
+    }
+
+    let mut tap_action = None;
+    let state = app_handle.state::<AppState>();
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    let suspended = *state.hotkeys_suspended.lock().await;
+    for (action, bindings) in hotkey_map_guard.iter().flatten().filter(|(action, _)| !suspended || action.is_suspend_exempt()) {
+        for binding in bindings.iter().filter(|binding| binding.hotkey.id() == hotkey_id && binding.then.is_none()) {
+            match binding.trigger {
+                Trigger::DoubleTap => bound.double_tap = true,
+                Trigger::Hold => bound.hold = true,
+                Trigger::Tap => tap_action = Some(*action),
             }
         }
     }
+
+    let repeat = tap_action.filter(|action| action.is_repeatable() && bound == BoundTriggers::default());
+    (bound, repeat)
+}
+
+// Any hotkey pattern detected while a chord is armed disarms it. A tap on a follow-up key of the chord runs
+// the chord's action, anything else is then handled as if no chord had been armed
+pub async fn handle_hotkey_event(
+    app_handle: &tauri::AppHandle,
+    armed_chord: &mut Option<ArmedChord>,
+    hotkey_id: u32,
+    trigger: Trigger,
+) {
+    let activity = start_activity(app_handle, hotkey_id, trigger).await;
+    if let Some(chord) = armed_chord.take() {
+        sync_chord_follow_ups(app_handle, HashSet::new()).await;
+        if trigger == Trigger::Tap
+            && let Some(action) = chord.follow_ups.iter().find(|(hotkey, _)| hotkey.id() == hotkey_id).map(|(_, action)| *action)
+        {
+            run_action(app_handle, action, activity).await;
+            return;
+        }
+    }
+
+    let state = app_handle.state::<AppState>();
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    let Some(hotkey_map) = hotkey_map_guard.as_ref() else {
+        finish_activity(app_handle, activity, None, ActivityOutcome::NotBound).await;
+        return;
+    };
+    let suspended = *state.hotkeys_suspended.lock().await;
+
+    // Find the bindings for this hotkey and press pattern, validation makes sure they are either one plain binding
+    // or only chords. Chords are armed by a tap of their leader. While suspended the hotkey is only still registered
+    // for the suspend toggle, so other bindings sharing it, like chords with the same leader, are left out
+    let matches: Vec<(Action, Binding)> = hotkey_map
+        .iter()
+        .filter(|(action, _)| !suspended || action.is_suspend_exempt())
+        .flat_map(|(action, bindings)| bindings.iter().map(move |binding| (*action, *binding)))
+        .filter(|(_, binding)| binding.hotkey.id() == hotkey_id && binding.trigger == trigger)
+        .collect();
+    drop(hotkey_map_guard);
+
+    match matches.as_slice() {
+        // E.g. a tap on a hotkey that only has a hold binding
+        [] => {
+            log::debug!("Hotkey ID '{}' has no binding for {:?}", hotkey_id, trigger);
+            finish_activity(app_handle, activity, None, ActivityOutcome::NotBound).await;
+        }
+        [(action, Binding { then: None, .. })] => run_action(app_handle, *action, activity).await,
+        chords => {
+            let follow_ups: HashMap<HotKey, Action> =
+                chords.iter().filter_map(|(action, binding)| binding.then.map(|then| (then, *action))).collect();
+            log::info!("Handle_Hotkey_Event: Chord armed, waiting for one of {} follow-up keys", follow_ups.len());
+            sync_chord_follow_ups(app_handle, follow_ups.keys().copied().collect()).await;
+            *armed_chord = Some(ArmedChord { follow_ups, deadline: Instant::now() + CHORD_TIMEOUT });
+            finish_activity(app_handle, activity, None, ActivityOutcome::ChordArmed).await;
+        }
+    }
+}
+
+// Spotify actions are handed to the action queue so the listener never waits on the API
+async fn run_action(app_handle: &tauri::AppHandle, action: Action, activity: u64) {
+    run_repeated_action(app_handle, action, 1, activity).await;
+}
+
+async fn run_repeated_action(app_handle: &tauri::AppHandle, action: Action, steps: u32, activity: u64) {
+    resolve_activity(app_handle, activity, action).await;
+    if action.uses_spotify() {
+        enqueue_action(app_handle, action, i32::try_from(steps).unwrap_or(i32::MAX), activity).await;
+        return;
+    }
+    // Macros can wait between steps, run them on their own so the listener keeps handling keys. Their steps still
+    // go through the action queue one by one
+    if let Action::Macro(_) = action {
+        let app_handle = app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            let outcome = action_outcome(action, action.execute(&app_handle).await);
+            finish_activity(&app_handle, activity, Some(action), outcome).await;
+        });
+        return;
+    }
+
+    let outcome = action_outcome(action, action.execute(app_handle).await);
+    finish_activity(app_handle, activity, Some(action), outcome).await;
+}
+
+// Registers the follow-up keys of an armed chord on top of the normal set, pass an empty set to drop them again
+async fn sync_chord_follow_ups(app_handle: &tauri::AppHandle, follow_ups: HashSet<HotKey>) {
+    let state = app_handle.state::<AppState>();
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    let Some(hotkey_map) = hotkey_map_guard.as_ref() else {
+        return;
+    };
+
+    let mut desired = desired_hotkeys(hotkey_map, *state.hotkeys_suspended.lock().await);
+    desired.extend(follow_ups.iter().copied());
+    match sync_registered_hotkeys(app_handle, desired).await {
+        Ok(failures) => {
+            for (hotkey, e) in failures.iter().filter(|(hotkey, _)| follow_ups.contains(hotkey)) {
+                log::error!("Sync_Chord_Follow_Ups: Failed to register follow-up key {:?}: {}", hotkey, e);
+            }
+        }
+        Err(e) => log::error!("Sync_Chord_Follow_Ups: Failed to sync chord keys: {}", e),
+    }
+}
+
+// Releases every binding back to the OS except the suspend toggle, or registers them again.
+// hotkey_hashmap keeps the full set while suspended so resuming restores exactly what was bound. Suspension is
+// not persisted, so a restart always comes back with every hotkey registered
+pub async fn set_hotkeys_suspended(app_handle: &tauri::AppHandle, suspended: bool) -> Result<bool, String> {
+    let state = app_handle.state::<AppState>();
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    let mut suspended_guard = state.hotkeys_suspended.lock().await;
+    if *suspended_guard == suspended {
+        return Ok(suspended);
+    }
+    let Some(hotkey_map) = hotkey_map_guard.as_ref() else {
+        log::error!("Set_Hotkeys_Suspended: Hotkey hashmap is not initialized!");
+        return Err("Hotkey hashmap is not initialized".to_string());
+    };
+
+    let failures = sync_registered_hotkeys(app_handle, desired_hotkeys(hotkey_map, suspended)).await?;
+    *suspended_guard = suspended;
+    log::info!("Set_Hotkeys_Suspended: Hotkeys {}", if suspended { "suspended" } else { "resumed" });
+
+    // Another app may have grabbed a combo while we were suspended
+    let failed: Vec<BindingResult> = hotkey_map
+        .iter()
+        .flat_map(|(action, bindings)| bindings.iter().map(move |binding| (*action, binding)))
+        .filter_map(|(action, binding)| {
+            let e = failures.get(&binding.hotkey)?;
+            Some(BindingResult::failed(action, format_binding(binding), BindingError::RegistrationFailed(e.clone())))
+        })
+        .collect();
+    drop(suspended_guard);
+    drop(hotkey_map_guard);
+
+    for result in &failed {
+        log::error!(
+            "Set_Hotkeys_Suspended: Failed to register '{}' for {:?}: {}",
+            result.accelerator,
+            result.action,
+            result.message.as_deref().unwrap_or_default()
+        );
+    }
+    if !failed.is_empty() {
+        state.binding_errors.lock().await.extend(failed.iter().cloned());
+        if let Err(e) = app_handle.emit("hotkey-binding-errors", failed) {
+            log::error!("Set_Hotkeys_Suspended: Failed to emit binding errors: {}", e);
+        }
+    }
+
+    if let Some(menu_item) = app_handle.try_state::<SuspendMenuItem>()
+        && let Err(e) = menu_item.0.set_checked(suspended)
+    {
+        log::error!("Set_Hotkeys_Suspended: Failed to update tray menu: {}", e);
+    }
+    if let Err(e) = app_handle.emit("hotkeys-suspended-changed", suspended) {
+        log::error!("Set_Hotkeys_Suspended: Failed to emit suspend state: {}", e);
+    }
+    Ok(suspended)
+}
+
+pub async fn toggle_hotkeys_suspended(app_handle: &tauri::AppHandle) -> Result<bool, String> {
+    let suspended = *app_handle.state::<AppState>().hotkeys_suspended.lock().await;
+    set_hotkeys_suspended(app_handle, !suspended).await
+}
+
+#[tauri::command]
+pub async fn suspend_hotkeys(app_handle: tauri::AppHandle) -> Result<bool, String> {
+    set_hotkeys_suspended(&app_handle, true).await
+}
+
+#[tauri::command]
+pub async fn resume_hotkeys(app_handle: tauri::AppHandle) -> Result<bool, String> {
+    set_hotkeys_suspended(&app_handle, false).await
+}
+
+#[tauri::command]
+pub async fn get_hotkeys_suspended(state: State<'_, AppState>) -> Result<bool, String> {
+    Ok(*state.hotkeys_suspended.lock().await)
+}
+
+#[tauri::command]
+pub async fn get_press_thresholds(state: State<'_, AppState>) -> Result<PressThresholds, String> {
+    Ok(*state.press_thresholds.lock().await)
+}
+
+// Saved next to the bindings so they survive restarts, and picked up by the listener on the next key press
+#[tauri::command]
+pub async fn set_press_thresholds(state: State<'_, AppState>, thresholds: PressThresholds) -> Result<(), String> {
+    if thresholds.double_tap_ms == 0 || thresholds.hold_ms == 0 || thresholds.repeat_interval_ms == 0 {
+        return Err("Double tap, hold and repeat interval times must be greater than 0 ms".to_string());
+    }
+
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    HotkeyCache::update(&hotkey_map_guard, |cache| {
+        cache.press_thresholds = thresholds;
+        Ok(())
+    })?;
+
+    *state.press_thresholds.lock().await = thresholds;
+    log::info!("Set_Press_Thresholds: {:?}", thresholds);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(
+        bindings: &[(Action, &str)],
+        macros: &BTreeMap<u32, Macro>,
+    ) -> (HashMap<Action, Vec<Binding>>, Vec<BindingResult>) {
+        let mut by_action: HashMap<Action, Vec<String>> = HashMap::new();
+        for (action, accelerator) in bindings {
+            by_action.entry(*action).or_default().push(accelerator.to_string());
+        }
+        validate_bindings(by_action, macros)
+    }
+
+    fn conflicts(results: &[BindingResult]) -> Vec<(Action, Vec<Action>)> {
+        results
+            .iter()
+            .filter_map(|result| match &result.error {
+                Some(BindingError::Conflict(others)) => Some((result.action, others.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_combo_with_different_triggers_is_allowed() {
+        let (hotkeys, results) = validate(
+            &[
+                (Action::PlayPause, "CTRL + ALT + P"),
+                (Action::NextTrack, "CTRL + ALT + P @double"),
+                (Action::LikeTrack, "CTRL + ALT + P @hold"),
+            ],
+            &BTreeMap::new(),
+        );
+        assert!(results.iter().all(|result| result.error.is_none()));
+        assert_eq!(hotkeys.len(), 3);
+    }
+
+    #[test]
+    fn same_combo_and_trigger_fails_on_both_sides() {
+        let (hotkeys, results) =
+            validate(&[(Action::PlayPause, "CTRL + ALT + P"), (Action::NextTrack, "ctrl+alt+p")], &BTreeMap::new());
+        assert_eq!(
+            conflicts(&results),
+            vec![(Action::PlayPause, vec![Action::NextTrack]), (Action::NextTrack, vec![Action::PlayPause])]
+        );
+        assert!(hotkeys.is_empty());
+    }
+
+    #[test]
+    fn plain_hotkey_that_leads_a_chord_fails_on_both_sides() {
+        let (hotkeys, results) = validate(
+            &[
+                (Action::PlayPause, "CTRL + ALT + S"),
+                (Action::NextTrack, "CTRL + ALT + S > N"),
+                (Action::PrevTrack, "CTRL + ALT + S > B"),
+            ],
+            &BTreeMap::new(),
+        );
+        assert_eq!(
+            conflicts(&results),
+            vec![
+                (Action::PlayPause, vec![Action::NextTrack, Action::PrevTrack]),
+                (Action::NextTrack, vec![Action::PlayPause]),
+                (Action::PrevTrack, vec![Action::PlayPause]),
+            ]
+        );
+        assert!(hotkeys.is_empty());
+
+        // Chords sharing a leader are fine on their own
+        let (hotkeys, results) =
+            validate(&[(Action::NextTrack, "CTRL + ALT + S > N"), (Action::PrevTrack, "CTRL + ALT + S > B")], &BTreeMap::new());
+        assert!(results.iter().all(|result| result.error.is_none()));
+        assert_eq!(hotkeys.len(), 2);
+    }
+
+    #[test]
+    fn bindings_to_unknown_macros_are_rejected() {
+        let bindings = [(Action::Macro(3), "F13")];
+        let (hotkeys, results) = validate(&bindings, &BTreeMap::new());
+        assert!(matches!(results[0].error, Some(BindingError::UnknownMacro(3))));
+        assert!(hotkeys.is_empty());
+
+        let macros = BTreeMap::from([(3, Macro { name: "Focus".to_string(), steps: Vec::new() })]);
+        let (hotkeys, results) = validate(&bindings, &macros);
+        assert!(results[0].error.is_none());
+        assert_eq!(hotkeys.len(), 1);
+    }
 }
@@ -0,0 +1,375 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::accelerator::Binding;
+use crate::action::Action;
+use crate::macros::Macro;
+use crate::persist::write_atomic;
+use crate::press::PressThresholds;
+use crate::{APP_CACHE_DIR, HOTKEY_CACHE};
+
+// Bump this and add a step to MIGRATIONS whenever the shape of the cache changes
+pub const HOTKEY_CACHE_VERSION: u32 = 3;
+
+// Version of the exported profile format, independent from the cache version. v2 added `macros`
+pub const PROFILE_FILE_VERSION: u32 = 2;
+
+// Profile created for new installs and for caches migrated from before profiles existed
+pub const DEFAULT_PROFILE: &str = "default";
+
+type Migration = fn(Value) -> Result<Value, String>;
+
+// Guard of AppState.hotkey_hashmap. The hotkey lock also guards the cache file, so every read-modify-write of the
+// cache goes through HotkeyCache::update, which only takes it with this guard held
+pub type HotkeyLock<'a> = tokio::sync::MutexGuard<'a, Option<HashMap<Action, Vec<Binding>>>>;
+
+// Each step upgrades the cache JSON by exactly one version, MIGRATIONS[0] goes from v1 to v2 and so on
+const MIGRATIONS: &[Migration] = &[migrate_v1_to_v2, migrate_v2_to_v3];
+
+// Shareable binding layout written by export_hotkeys and read by import_hotkeys.
+// Uses a BTreeMap so exported files are stable and diff nicely
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HotkeyProfileFile {
+    pub version: u32,
+    pub bindings: BTreeMap<Action, Vec<String>>,
+    // Macros used by `bindings`, keyed by their id on the exporting machine
+    #[serde(default)]
+    pub macros: BTreeMap<u32, Macro>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFileFormat {
+    Json,
+    Toml,
+}
+
+impl ProfileFileFormat {
+    // Picked from the file extension, anything that is not .toml is treated as JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ProfileFileFormat::Toml,
+            _ => ProfileFileFormat::Json,
+        }
+    }
+}
+
+impl HotkeyProfileFile {
+    pub fn new(bindings: HashMap<Action, Vec<String>>, macros: BTreeMap<u32, Macro>) -> Self {
+        Self {
+            version: PROFILE_FILE_VERSION,
+            bindings: bindings.into_iter().collect(),
+            macros,
+        }
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), String> {
+        let data = match ProfileFileFormat::from_path(path) {
+            ProfileFileFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string())?,
+            ProfileFileFormat::Toml => toml::to_string_pretty(self).map_err(|e| e.to_string())?,
+        };
+        // Written in place, the file is the user's own copy and can simply be exported again
+        fs::write(path, data).map_err(|e| e.to_string())
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let profile: Self = match ProfileFileFormat::from_path(path) {
+            ProfileFileFormat::Json => serde_json::from_str(&data).map_err(|e| e.to_string())?,
+            ProfileFileFormat::Toml => toml::from_str(&data).map_err(|e| e.to_string())?,
+        };
+
+        if profile.version == 0 || profile.version > PROFILE_FILE_VERSION {
+            return Err(format!(
+                "Hotkey profile version {} is not supported, the latest supported version is {}",
+                profile.version, PROFILE_FILE_VERSION
+            ));
+        }
+        Ok(profile)
+    }
+}
+
+// Why the hotkey cache could not be loaded. Only `Corrupt` means the file itself is unreadable
+#[derive(Debug)]
+pub enum CacheLoadError {
+    Missing,
+    Corrupt(String),
+    Unsupported(String),
+    Io(String),
+}
+
+impl fmt::Display for CacheLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheLoadError::Missing => write!(f, "hotkey cache does not exist"),
+            CacheLoadError::Corrupt(e) => write!(f, "hotkey cache is corrupt: {}", e),
+            CacheLoadError::Unsupported(e) => write!(f, "{}", e),
+            CacheLoadError::Io(e) => write!(f, "failed to read hotkey cache: {}", e),
+        }
+    }
+}
+
+// Persist Hotkeys.
+// Bindings of global actions (see Action::is_global) are shared by every profile so they keep working after a switch
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HotkeyCache {
+    pub version: u32,
+    pub active_profile: String,
+    pub profiles: BTreeMap<String, HotkeyProfile>,
+    #[serde(default)]
+    pub global_bindings: HashMap<Action, Vec<String>>,
+    #[serde(default)]
+    pub press_thresholds: PressThresholds,
+    // Keyed by the id used in Action::Macro
+    #[serde(default)]
+    pub macros: BTreeMap<u32, Macro>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct HotkeyProfile {
+    pub bindings: HashMap<Action, Vec<String>>,
+}
+
+impl Default for HotkeyCache {
+    fn default() -> Self {
+        Self {
+            version: HOTKEY_CACHE_VERSION,
+            active_profile: DEFAULT_PROFILE.to_string(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), HotkeyProfile::default())]),
+            global_bindings: HashMap::new(),
+            press_thresholds: PressThresholds::default(),
+            macros: BTreeMap::new(),
+        }
+    }
+}
+
+impl HotkeyCache {
+    pub fn path() -> PathBuf {
+        APP_CACHE_DIR
+            .get()
+            .expect("hotkey_cache: APP_CACHE_DIR not initialized")
+            .join(HOTKEY_CACHE)
+    }
+
+    // Loads the cache, lets `modify` change it and saves it again. Nothing is saved when `modify` fails
+    pub fn update<T>(
+        _hotkey_lock: &HotkeyLock<'_>,
+        modify: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let path = Self::path();
+        let mut cache = Self::load_or_default(path.clone())?;
+        let result = modify(&mut cache)?;
+        cache.save_to_file(path)?;
+        Ok(result)
+    }
+
+    // Bindings of the given profile merged with the global ones
+    pub fn profile_bindings(&self, name: &str) -> Option<HashMap<Action, Vec<String>>> {
+        let profile = self.profiles.get(name)?;
+        let mut bindings = profile.bindings.clone();
+        bindings.extend(self.global_bindings.iter().map(|(action, hotkeys)| (*action, hotkeys.clone())));
+        Some(bindings)
+    }
+
+    pub fn active_bindings(&self) -> HashMap<Action, Vec<String>> {
+        self.profile_bindings(&self.active_profile).unwrap_or_default()
+    }
+
+    // Stores global actions in global_bindings and everything else in the active profile
+    pub fn set_active_bindings(&mut self, bindings: HashMap<Action, Vec<String>>) {
+        let (global, profile): (HashMap<_, _>, HashMap<_, _>) =
+            bindings.into_iter().partition(|(action, _)| action.is_global());
+        self.global_bindings = global;
+        self.profiles.entry(self.active_profile.clone()).or_default().bindings = profile;
+    }
+
+    // Loads the cache for a read-modify-write, starting from an empty cache if none was saved yet
+    pub fn load_or_default(path: PathBuf) -> Result<Self, String> {
+        match Self::load_from_file(path) {
+            Ok(cache) => Ok(cache),
+            Err(CacheLoadError::Missing) => Ok(Self::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn save_to_file(&self, path: PathBuf) -> Result<(), String> {
+        let data = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        write_atomic(&path, data.as_bytes()).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // Older caches are migrated to the current version and written back,
+    // after copying the original file next to it so a failed migration never loses bindings
+    pub fn load_from_file(path: PathBuf) -> Result<Self, CacheLoadError> {
+        let data = fs::read_to_string(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => CacheLoadError::Missing,
+            _ => CacheLoadError::Io(e.to_string()),
+        })?;
+        let mut value: Value = serde_json::from_str(&data).map_err(|e| CacheLoadError::Corrupt(e.to_string()))?;
+
+        let version = match cache_version(&value) {
+            Some(version) if (1..=HOTKEY_CACHE_VERSION).contains(&version) => version,
+            _ => {
+                return Err(CacheLoadError::Unsupported(format!(
+                    "Hotkey cache version {} is not supported, the latest supported version is {}",
+                    value["version"], HOTKEY_CACHE_VERSION
+                )));
+            }
+        };
+        if version == HOTKEY_CACHE_VERSION {
+            return serde_json::from_value(value).map_err(|e| CacheLoadError::Corrupt(e.to_string()));
+        }
+
+        let backup_path = path.with_extension(format!("v{}.bak", version));
+        fs::copy(&path, &backup_path)
+            .map_err(|e| CacheLoadError::Io(format!("Failed to back up hotkey cache before migrating: {}", e)))?;
+        log::info!("Hotkey_Cache: Backed up version {} cache to {:?}", version, backup_path);
+
+        for (step, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+            log::info!("Hotkey_Cache: Migrating hotkey cache from version {} to {}", step + 1, step + 2);
+            value = migration(value).map_err(CacheLoadError::Corrupt)?;
+        }
+
+        let cache: Self = serde_json::from_value(value).map_err(|e| CacheLoadError::Corrupt(e.to_string()))?;
+        if let Err(e) = cache.save_to_file(path) {
+            log::error!("Hotkey_Cache: Failed to write migrated hotkey cache: {}", e);
+        }
+        Ok(cache)
+    }
+}
+
+// Caches written before versioning was added have no version field and count as version 1.
+// None for a version that is not a u32, e.g. from a far newer app, instead of truncating it into a supported one
+fn cache_version(value: &Value) -> Option<u32> {
+    match value.get("version") {
+        None => Some(1),
+        Some(version) => version.as_u64().and_then(|version| u32::try_from(version).ok()),
+    }
+}
+
+// v1 stored `string_hotkeys`, with either a single accelerator or a list of them per action
+fn migrate_v1_to_v2(value: Value) -> Result<Value, String> {
+    let string_hotkeys = value
+        .get("string_hotkeys")
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+
+    let mut bindings = serde_json::Map::new();
+    for (action, hotkeys) in string_hotkeys {
+        let hotkeys = match hotkeys {
+            Value::String(hotkey_str) => vec![Value::String(hotkey_str)],
+            Value::Array(hotkey_strs) => hotkey_strs,
+            other => return Err(format!("Unexpected bindings for '{}' in version 1 cache: {}", action, other)),
+        };
+        bindings.insert(action, Value::Array(hotkeys));
+    }
+
+    Ok(json!({ "version": 2, "bindings": bindings }))
+}
+
+// v3 moved the bindings into a "default" profile
+fn migrate_v2_to_v3(value: Value) -> Result<Value, String> {
+    let bindings = value.get("bindings").cloned().unwrap_or_else(|| json!({}));
+    if !bindings.is_object() {
+        return Err(format!("Unexpected bindings in version 2 cache: {}", bindings));
+    }
+
+    Ok(json!({
+        "version": 3,
+        "active_profile": DEFAULT_PROFILE,
+        "profiles": { DEFAULT_PROFILE: { "bindings": bindings } },
+        "global_bindings": {},
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fresh directory per test, removed again by the test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hotkey_cache_test_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn v1_bindings_become_lists_in_either_shape() {
+        let v1 = json!({ "string_hotkeys": { "play_pause": "CTRL + ALT + P", "next_track": ["CTRL + ALT + N", "F13"] } });
+        let v2 = migrate_v1_to_v2(v1).unwrap();
+        assert_eq!(
+            v2,
+            json!({ "version": 2, "bindings": { "play_pause": ["CTRL + ALT + P"], "next_track": ["CTRL + ALT + N", "F13"] } })
+        );
+
+        assert!(migrate_v1_to_v2(json!({ "string_hotkeys": { "play_pause": 5 } })).is_err());
+    }
+
+    #[test]
+    fn v2_bindings_move_into_the_default_profile() {
+        let v3 = migrate_v2_to_v3(json!({ "version": 2, "bindings": { "play_pause": ["CTRL + ALT + P"] } })).unwrap();
+        assert_eq!(v3["version"], 3);
+        assert_eq!(v3["active_profile"], DEFAULT_PROFILE);
+        assert_eq!(v3["profiles"][DEFAULT_PROFILE]["bindings"], json!({ "play_pause": ["CTRL + ALT + P"] }));
+
+        assert!(migrate_v2_to_v3(json!({ "version": 2, "bindings": ["CTRL + ALT + P"] })).is_err());
+    }
+
+    #[test]
+    fn old_cache_is_backed_up_and_migrated_without_losing_bindings() {
+        let dir = test_dir("migrate");
+        let path = dir.join(".hotkey_cache.json");
+        let v1 = r#"{"string_hotkeys":{"play_pause":"CTRL + ALT + P","next_track":["CTRL + ALT + N","F13"]}}"#;
+        fs::write(&path, v1).unwrap();
+
+        let cache = HotkeyCache::load_from_file(path.clone()).unwrap();
+        assert_eq!(cache.version, HOTKEY_CACHE_VERSION);
+        let bindings = cache.active_bindings();
+        assert_eq!(bindings[&Action::PlayPause], vec!["CTRL + ALT + P"]);
+        assert_eq!(bindings[&Action::NextTrack], vec!["CTRL + ALT + N", "F13"]);
+
+        // The original is kept as is and the migrated cache is written back
+        assert_eq!(fs::read_to_string(path.with_extension("v1.bak")).unwrap(), v1);
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(cache_version(&saved), Some(HOTKEY_CACHE_VERSION));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cache_from_a_newer_version_is_rejected_untouched() {
+        let dir = test_dir("future");
+        let path = dir.join(".hotkey_cache.json");
+        // The last one would wrap around to version 2 if it was truncated to a u32
+        let versions = [json!(HOTKEY_CACHE_VERSION + 1), json!(0), json!("3"), json!(u64::from(u32::MAX) + 3)];
+        for version in versions {
+            let future = json!({ "version": version, "bindings": {} }).to_string();
+            fs::write(&path, &future).unwrap();
+
+            assert!(matches!(HotkeyCache::load_from_file(path.clone()), Err(CacheLoadError::Unsupported(_))), "{}", version);
+            assert_eq!(fs::read_to_string(&path).unwrap(), future);
+            assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn v1_profile_files_still_import_without_macros() {
+        let dir = test_dir("profile_v1");
+        let path = dir.join("hotkeys.json");
+        fs::write(&path, json!({ "version": 1, "bindings": { "play_pause": ["CTRL + ALT + P"] } }).to_string()).unwrap();
+
+        let profile = HotkeyProfileFile::load_from_file(&path).unwrap();
+        assert_eq!(profile.bindings[&Action::PlayPause], vec!["CTRL + ALT + P"]);
+        assert!(profile.macros.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
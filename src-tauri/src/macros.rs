@@ -0,0 +1,271 @@
+use rspotify::model::{AlbumId, ArtistId, PlayContextId, PlaylistId, ShowId};
+use rspotify::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::action::Action;
+use crate::api::AuthResult;
+use crate::hotkey_cache::HotkeyCache;
+use crate::queue::run_queued_macro_step;
+use crate::AppState;
+
+// A user defined sequence of playback steps, bound to hotkeys as Action::Macro(id).
+// Stored in the hotkey cache and shared by every profile
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Macro {
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MacroStep {
+    #[serde(flatten)]
+    pub command: StepCommand,
+    // Wait before running this step
+    #[serde(default)]
+    pub delay_ms: u64,
+    // Keep going with the next step when this one fails, otherwise the macro stops here
+    #[serde(default)]
+    pub continue_on_error: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StepCommand {
+    Action { action: Action },
+    SetVolume { percent: u8 },
+    // Device name as shown in Spotify, or its id
+    TransferPlayback { device: String },
+    // spotify:playlist:... style URI or an open.spotify.com link of a playlist, album, artist or show
+    PlayContext { uri: String },
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MacroStepResult {
+    pub step: usize,
+    pub error: Option<String>,
+}
+
+// Outcome of one macro run, emitted as "macro-finished" so the frontend can point at the failed step
+#[derive(Serialize, Debug, Clone)]
+pub struct MacroReport {
+    pub macro_id: u32,
+    pub name: String,
+    pub steps: Vec<MacroStepResult>,
+    // Set when a failed step stopped the macro
+    pub aborted: bool,
+}
+
+fn validate_macro(macro_def: &Macro) -> Result<(), String> {
+    if macro_def.name.trim().is_empty() {
+        return Err("Macro name cannot be empty".to_string());
+    }
+    if macro_def.steps.is_empty() {
+        return Err("Macro needs at least one step".to_string());
+    }
+    for (index, step) in macro_def.steps.iter().enumerate() {
+        let error = match &step.command {
+            StepCommand::Action { action: Action::Macro(_) } => Some("macros cannot run other macros".to_string()),
+            StepCommand::SetVolume { percent } if *percent > 100 => Some("volume must be between 0 and 100".to_string()),
+            StepCommand::TransferPlayback { device } if device.trim().is_empty() => Some("device cannot be empty".to_string()),
+            StepCommand::PlayContext { uri } => parse_context_uri(uri).err(),
+            _ => None,
+        };
+        if let Some(error) = error {
+            return Err(format!("Step {}: {}", index + 1, error));
+        }
+    }
+    Ok(())
+}
+
+// Accepts "spotify:playlist:<id>" and "https://open.spotify.com/playlist/<id>?si=..." for playlists, albums, artists and shows
+fn parse_context_uri(uri: &str) -> Result<PlayContextId<'static>, String> {
+    let uri = uri.trim();
+    let uri = match uri.strip_prefix("https://open.spotify.com/") {
+        Some(path) => {
+            let path = path.split('?').next().unwrap_or_default();
+            format!("spotify:{}", path.replace('/', ":"))
+        }
+        None => uri.to_string(),
+    };
+
+    let context = match uri.split(':').nth(1) {
+        Some("playlist") => PlaylistId::from_uri(&uri).map(PlayContextId::from),
+        Some("album") => AlbumId::from_uri(&uri).map(PlayContextId::from),
+        Some("artist") => ArtistId::from_uri(&uri).map(PlayContextId::from),
+        Some("show") => ShowId::from_uri(&uri).map(PlayContextId::from),
+        _ => return Err(format!("'{}' is not a playlist, album, artist or show", uri)),
+    };
+    context
+        .map(PlayContextId::into_static)
+        .map_err(|e| format!("'{}' is not a valid Spotify URI: {}", uri, e))
+}
+
+#[tauri::command]
+pub async fn list_macros() -> Result<BTreeMap<u32, Macro>, String> {
+    Ok(HotkeyCache::load_or_default(HotkeyCache::path())?.macros)
+}
+
+// Creates a macro when `id` is None, otherwise replaces it. Returns the id to bind it with
+#[tauri::command]
+pub async fn save_macro(state: State<'_, AppState>, id: Option<u32>, macro_def: Macro) -> Result<u32, String> {
+    validate_macro(&macro_def)?;
+
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    HotkeyCache::update(&hotkey_map_guard, |cache| {
+        let id = match id {
+            Some(id) if !cache.macros.contains_key(&id) => return Err(format!("Macro {} does not exist", id)),
+            Some(id) => id,
+            None => next_macro_id(&cache.macros),
+        };
+        log::info!("Save_Macro: Saving macro {} '{}' with {} steps", id, macro_def.name, macro_def.steps.len());
+        cache.macros.insert(id, macro_def);
+        Ok(id)
+    })
+}
+
+fn next_macro_id(macros: &BTreeMap<u32, Macro>) -> u32 {
+    macros.keys().next_back().map_or(1, |last| last + 1)
+}
+
+// Adds the macros of an imported profile file to `macros` and returns the id each one got here, ids of the exporting
+// machine may already be taken. A macro identical to an existing one reuses it, so importing twice adds nothing
+pub fn merge_imported_macros(
+    macros: &mut BTreeMap<u32, Macro>,
+    imported: BTreeMap<u32, Macro>,
+) -> Result<HashMap<u32, u32>, String> {
+    let mut ids = HashMap::new();
+    for (imported_id, macro_def) in imported {
+        validate_macro(&macro_def).map_err(|e| format!("Macro '{}': {}", macro_def.name, e))?;
+        let id = match macros.iter().find(|(_, existing)| **existing == macro_def) {
+            Some((id, _)) => *id,
+            None => {
+                let id = next_macro_id(macros);
+                macros.insert(id, macro_def);
+                id
+            }
+        };
+        ids.insert(imported_id, id);
+    }
+    Ok(ids)
+}
+
+// Macros that still have hotkeys in any profile are kept, so no binding ends up pointing at nothing
+#[tauri::command]
+pub async fn delete_macro(state: State<'_, AppState>, id: u32) -> Result<(), String> {
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    let action = Action::Macro(id);
+    HotkeyCache::update(&hotkey_map_guard, |cache| {
+        if let Some((profile, _)) = cache
+            .profiles
+            .iter()
+            .find(|(_, profile)| profile.bindings.get(&action).is_some_and(|hotkeys| !hotkeys.is_empty()))
+        {
+            return Err(format!("Macro {} still has hotkeys in profile '{}', remove them first", id, profile));
+        }
+        if cache.macros.remove(&id).is_none() {
+            return Err(format!("Macro {} does not exist", id));
+        }
+        for profile in cache.profiles.values_mut() {
+            profile.bindings.remove(&action);
+        }
+        Ok(())
+    })?;
+    log::info!("Delete_Macro: Deleted macro {}", id);
+    Ok(())
+}
+
+// Lets the frontend try a macro without binding it
+#[tauri::command]
+pub async fn run_macro(app_handle: AppHandle, id: u32) -> Result<MacroReport, String> {
+    execute_macro(&app_handle, id).await
+}
+
+// Entry point for Action::Macro, failed steps are reported through the macro report
+pub async fn run_macro_action(app_handle: &AppHandle, id: u32) -> Result<AuthResult, String> {
+    let report = execute_macro(app_handle, id).await?;
+    match report.steps.iter().find_map(|step| step.error.as_ref().map(|error| (step.step, error))) {
+        Some((step, error)) => Ok(AuthResult::Error {
+            message: format!("Macro '{}' step {} failed: {}", report.name, step + 1, error),
+        }),
+        None => Ok(AuthResult::Success { ok: "ok".to_string() }),
+    }
+}
+
+async fn execute_macro(app_handle: &AppHandle, id: u32) -> Result<MacroReport, String> {
+    let macro_def = HotkeyCache::load_or_default(HotkeyCache::path())?
+        .macros
+        .remove(&id)
+        .ok_or_else(|| format!("Macro {} does not exist", id))?;
+    log::info!("Execute_Macro: Running macro {} '{}'", id, macro_def.name);
+
+    let mut report = MacroReport { macro_id: id, name: macro_def.name.clone(), steps: Vec::new(), aborted: false };
+    for (index, step) in macro_def.steps.iter().enumerate() {
+        if step.delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(step.delay_ms)).await;
+        }
+
+        let error = run_queued_macro_step(app_handle, step.command.clone()).await.err();
+        if let Some(error) = &error {
+            log::error!("Execute_Macro: Step {} of macro '{}' failed: {}", index + 1, macro_def.name, error);
+        }
+        let failed = error.is_some();
+        report.steps.push(MacroStepResult { step: index, error });
+        if failed && !step.continue_on_error {
+            report.aborted = true;
+            break;
+        }
+    }
+
+    if let Err(e) = app_handle.emit("macro-finished", &report) {
+        log::error!("Execute_Macro: Failed to emit macro report: {}", e);
+    }
+    Ok(report)
+}
+
+// Runs one step on the action worker, see run_queued_macro_step
+pub async fn run_step(app_handle: &AppHandle, command: &StepCommand) -> Result<(), String> {
+    if let StepCommand::Action { action } = command {
+        return match action.execute(app_handle).await? {
+            AuthResult::Error { message } => Err(message),
+            AuthResult::NeedsAuth { .. } => Err("Spotify login required".to_string()),
+            AuthResult::Success { .. } => Ok(()),
+        };
+    }
+
+    let state = app_handle.state::<AppState>();
+    let spotify = state.spotify.lock().await;
+    let Some(spotify) = &*spotify else {
+        return Err("Spotify client not initialized".to_string());
+    };
+
+    match command {
+        StepCommand::Action { .. } => Ok(()),
+        StepCommand::SetVolume { percent } => {
+            spotify.volume(*percent, None).await.map_err(|e| format!("Failed to set volume: {}", e))?;
+            *state.volume.lock().await = *percent;
+            Ok(())
+        }
+        StepCommand::TransferPlayback { device } => {
+            let devices = spotify.device().await.map_err(|e| format!("Failed to list devices: {}", e))?;
+            let device_id = devices
+                .into_iter()
+                .find(|d| d.name.eq_ignore_ascii_case(device.trim()) || d.id.as_deref() == Some(device.trim()))
+                .and_then(|d| d.id)
+                .ok_or_else(|| format!("Device '{}' is not available", device))?;
+            spotify
+                .transfer_playback(&device_id, Some(true))
+                .await
+                .map_err(|e| format!("Failed to transfer playback: {}", e))
+        }
+        StepCommand::PlayContext { uri } => {
+            let context = parse_context_uri(uri)?;
+            spotify
+                .start_context_playback(context, None, None, None)
+                .await
+                .map_err(|e| format!("Failed to start playback: {}", e))
+        }
+    }
+}
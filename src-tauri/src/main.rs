@@ -3,41 +3,90 @@
     windows_subsystem = "windows"
 )]
 use tauri::{
-    menu::{MenuBuilder, MenuItemBuilder,}, tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent}, 
-    Manager, WebviewWindow, WindowEvent
+    menu::{CheckMenuItem, CheckMenuItemBuilder, MenuBuilder, MenuItemBuilder,}, tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent}, 
+    Manager, RunEvent, WebviewWindow, WindowEvent
 };
 
-use global_hotkey::hotkey::HotKey;
-use rspotify::AuthCodeSpotify;
+use rspotify::AuthCodePkceSpotify;
 use tauri_plugin_log::{Target, TargetKind};
 use log::LevelFilter;
 use std::{collections::HashMap, path::PathBuf, fs};
 use once_cell::sync::OnceCell;
 
+use crate::accelerator::Binding;
+use crate::action::Action;
+use crate::activity::*;
+use crate::press::PressThresholds;
+use crate::queue::{spawn_action_worker, ActionQueue};
+use crate::recorder::*;
+use crate::settings::*;
 use crate::api::*;
 use crate::hotkey::*;
+use crate::macros::*;
+use crate::profile::*;
 
+pub mod accelerator;
+pub mod activity;
+pub mod action;
 pub mod api;
 pub mod hotkey;
+pub mod hotkey_cache;
+pub mod macros;
+pub mod persist;
+pub mod press;
+pub mod profile;
+pub mod queue;
+pub mod recorder;
+pub mod settings;
 
 pub const HOTKEY_CACHE: &str = ".hotkey_cache.json";
+pub const USAGE_STATS_FILE: &str = ".usage_stats.json";
+pub const SETTINGS_FILE: &str = ".settings.json";
 pub const LOGS_FILENAME: &str = "global-hotkey-spotify-logs";
 pub static APP_CACHE_DIR: OnceCell<PathBuf> = OnceCell::new();
 
 // Main state of the app
 pub struct AppState {
-    pub spotify: tokio::sync::Mutex<Option<AuthCodeSpotify>>,
-    pub hotkey_hashmap: tokio::sync::Mutex<Option<HashMap<String, HotKey>>>,
+    pub spotify: tokio::sync::Mutex<Option<AuthCodePkceSpotify>>,
+    pub hotkey_hashmap: tokio::sync::Mutex<Option<HashMap<Action, Vec<Binding>>>>,
     pub volume: tokio::sync::Mutex<u8>,
+    pub binding_errors: tokio::sync::Mutex<Vec<BindingResult>>,
+    pub hotkey_cache_warning: tokio::sync::Mutex<Option<String>>,
+    pub hotkeys_suspended: tokio::sync::Mutex<bool>,
+    pub press_thresholds: tokio::sync::Mutex<PressThresholds>,
+    pub action_queue: tokio::sync::Mutex<ActionQueue>,
+    // Wakes the action worker when something is queued
+    pub action_queue_notify: tokio::sync::Notify,
+    // Set while the next pressed combo is being recorded instead of run
+    pub hotkey_recording: tokio::sync::Mutex<Option<HotkeyRecording>>,
+    pub activity_log: tokio::sync::Mutex<ActivityLog>,
+    // Loaded from USAGE_STATS_FILE in setup
+    pub usage_stats: tokio::sync::Mutex<UsageStats>,
+    // OAuth state of the login started by init_auth, the callback server only accepts a redirect carrying it
+    pub pending_auth_state: tokio::sync::Mutex<Option<String>>,
 }
 
+// Tray entry showing whether hotkeys are suspended, kept in sync when the toggle hotkey is used
+pub struct SuspendMenuItem(pub CheckMenuItem<tauri::Wry>);
+
 // Implement Default for AppState
 impl Default for AppState {
     fn default() -> Self {
         Self {
-            spotify: tokio::sync::Mutex::new(Some(init_spotify())),
+            // Built in setup, the client settings live in APP_CACHE_DIR
+            spotify: tokio::sync::Mutex::new(None),
             hotkey_hashmap: tokio::sync::Mutex::new(Some(HashMap::new())),
             volume: tokio::sync::Mutex::new(50),
+            binding_errors: tokio::sync::Mutex::new(Vec::new()),
+            hotkey_cache_warning: tokio::sync::Mutex::new(None),
+            hotkeys_suspended: tokio::sync::Mutex::new(false),
+            press_thresholds: tokio::sync::Mutex::new(PressThresholds::default()),
+            action_queue: tokio::sync::Mutex::new(ActionQueue::default()),
+            action_queue_notify: tokio::sync::Notify::new(),
+            hotkey_recording: tokio::sync::Mutex::new(None),
+            activity_log: tokio::sync::Mutex::new(ActivityLog::default()),
+            usage_stats: tokio::sync::Mutex::new(UsageStats::default()),
+            pending_auth_state: tokio::sync::Mutex::new(None),
         }
     }
 }
@@ -52,15 +101,23 @@ fn main() {
         .max_file_size(100000) // 100kb max file size
         .build())
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_dialog::init())
         .setup(|app| {
             log::info!("Setting up Tauri...");
             let app_cache_dir = app.path().app_cache_dir().unwrap();
             log::info!("App cache dir: {:?}", app_cache_dir);
             fs::create_dir_all(&app_cache_dir).expect("Failed to create app cache directory");
             APP_CACHE_DIR.set(app_cache_dir.clone()).expect("Failed to set APP_CACHE_DIR");            
-            if let Err(e) = ensure_hotkey_cache_file_exists(&app_cache_dir) {
+            if let Err(e) = ensure_hotkey_cache_file_exists() {
                 log::warn!("Failed to initialize hotkey cache file: {}", e);
             }
+
+            let settings = Settings::load(&settings_path());
+            let spotify = init_spotify(&settings.spotify);
+            tauri::async_runtime::block_on(async {
+                *app.state::<AppState>().spotify.lock().await = Some(spotify);
+            });
+            init_usage_stats(app.handle());
             
             // Setup autostart on desktop
             #[cfg(desktop)]
@@ -80,13 +137,16 @@ fn main() {
             
             // Setup hotkeys manager
             let app_handle_for_hotkey = app.app_handle().clone();
+            spawn_action_worker(app_handle_for_hotkey.clone());
             init_hotkeys(app_handle_for_hotkey);
 
             // System Tray setup
             let quit = MenuItemBuilder::new("Quit").id("quit").build(app).unwrap();
             let show = MenuItemBuilder::new("Show").id("show").build(app).unwrap();
+            let suspend = CheckMenuItemBuilder::new("Suspend Hotkeys").id("suspend").checked(false).build(app).unwrap();
+            app.manage(SuspendMenuItem(suspend.clone()));
             let menuitems = MenuBuilder::new(app)
-                .items(&[&quit, &show])
+                .items(&[&quit, &show, &suspend])
                 .build()
                 .unwrap();
 
@@ -106,10 +166,10 @@ fn main() {
                     }
                 }
                 WindowEvent::Focused(false) | WindowEvent::Resized(_) => {
-                    if matches!(window_for_events.is_minimized(), Ok(true)) {
-                        if let Err(err) = window_for_events.hide() {
-                            log::debug!("Failed to hide window after minimize: {err:?}");
-                        }
+                    if matches!(window_for_events.is_minimized(), Ok(true))
+                        && let Err(err) = window_for_events.hide()
+                    {
+                        log::debug!("Failed to hide window after minimize: {err:?}");
                     }
                 }
                 _ => {}
@@ -130,6 +190,14 @@ fn main() {
                         let window = app.get_webview_window("main").unwrap();
                         reveal_window(&window);
                     }
+                    "suspend" => {
+                        let app = app.clone();
+                        tauri::async_runtime::spawn(async move {
+                            if let Err(e) = toggle_hotkeys_suspended(&app).await {
+                                log::error!("Failed to toggle hotkey suspension from tray: {}", e);
+                            }
+                        });
+                    }
                     _ => {
                         log::error!("Menu item event: menu item was not handled");
                     }
@@ -175,11 +243,47 @@ fn main() {
             prev_track,
             volume_control_up,
             volume_control_down,
+            seek_forward,
+            seek_backward,
+            like_track,
             set_hotkeys,
+            set_bindings,
+            get_binding_errors,
+            get_hotkey_cache_warning,
+            get_supported_keys,
+            export_hotkeys,
+            import_hotkeys,
+            list_profiles,
+            create_profile,
+            rename_profile,
+            delete_profile,
+            activate_profile,
+            suspend_hotkeys,
+            resume_hotkeys,
+            get_hotkeys_suspended,
+            get_press_thresholds,
+            set_press_thresholds,
+            list_macros,
+            save_macro,
+            delete_macro,
+            run_macro,
+            record_hotkey,
+            cancel_hotkey_recording,
+            get_hotkey_activity,
+            get_usage_stats,
+            reset_usage_stats,
+            get_spotify_settings,
+            set_spotify_settings,
             return_loaded_hotkeys
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            if let RunEvent::Exit = event {
+                shutdown_hotkey_listener();
+                flush_usage_stats(app_handle);
+            }
+        });
 }
 
 // Helper to show window to screen
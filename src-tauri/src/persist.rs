@@ -0,0 +1,36 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+// Writes to a temporary file next to `path` and renames it over the target once it is flushed to disk,
+// so a crash or power loss mid-write leaves either the old or the new file but never a truncated one
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp_path = with_suffix(path, ".tmp");
+    {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    Ok(())
+}
+
+// Moves a file that failed to parse out of the way as `<name>.corrupt`, replacing any older quarantined copy
+pub fn quarantine_corrupt(path: &Path) -> io::Result<PathBuf> {
+    let corrupt_path = with_suffix(path, ".corrupt");
+    fs::rename(path, &corrupt_path)?;
+    Ok(corrupt_path)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
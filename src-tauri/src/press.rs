@@ -0,0 +1,453 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::accelerator::Trigger;
+use crate::action::Action;
+
+// Timings used to tell taps, double taps and holds apart and to repeat held keys.
+// Stored in the hotkey cache and shared by every profile, missing fields fall back to the defaults
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct PressThresholds {
+    // Longest gap between releasing the first tap and pressing the second one
+    pub double_tap_ms: u64,
+    // Shortest time a key has to stay down to count as a hold
+    pub hold_ms: u64,
+    // How long a repeatable key has to be held before it starts repeating
+    pub repeat_delay_ms: u64,
+    // Time between repeats once it does
+    pub repeat_interval_ms: u64,
+}
+
+impl Default for PressThresholds {
+    fn default() -> Self {
+        Self { double_tap_ms: 300, hold_ms: 500, repeat_delay_ms: 400, repeat_interval_ms: 100 }
+    }
+}
+
+impl PressThresholds {
+    fn double_tap(&self) -> Duration {
+        Duration::from_millis(self.double_tap_ms)
+    }
+
+    fn hold(&self) -> Duration {
+        Duration::from_millis(self.hold_ms)
+    }
+
+    fn repeat_delay(&self) -> Duration {
+        Duration::from_millis(self.repeat_delay_ms)
+    }
+
+    fn repeat_interval(&self) -> Duration {
+        Duration::from_millis(self.repeat_interval_ms)
+    }
+}
+
+// Which extra press patterns are bound on a hotkey. A tap only has to wait for a possible
+// double tap or hold when one is actually bound, so plain hotkeys keep firing right on release
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BoundTriggers {
+    pub double_tap: bool,
+    pub hold: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum KeyState {
+    Down { pressed_at: Instant, bound: BoundTriggers, hold_fired: bool },
+    WaitingForSecondTap { released_at: Instant },
+    SecondDown,
+}
+
+// Turns the Pressed/Released stream of GlobalHotKeyEvent into taps, double taps and holds.
+// Time is passed in by the caller so the logic can be tested without sleeping
+#[derive(Debug, Default)]
+pub struct PressDetector {
+    pub thresholds: PressThresholds,
+    keys: HashMap<u32, KeyState>,
+}
+
+impl PressDetector {
+    pub fn new(thresholds: PressThresholds) -> Self {
+        Self { thresholds, keys: HashMap::new() }
+    }
+
+    // Returns a tap that was still waiting for a second press when its window had already run out
+    pub fn pressed(&mut self, id: u32, bound: BoundTriggers, now: Instant) -> Option<Trigger> {
+        let down = KeyState::Down { pressed_at: now, bound, hold_fired: false };
+        match self.keys.get(&id).copied() {
+            None => {
+                self.keys.insert(id, down);
+                None
+            }
+            // Key repeat from the OS while the key is held
+            Some(KeyState::Down { .. } | KeyState::SecondDown) => None,
+            Some(KeyState::WaitingForSecondTap { released_at }) => {
+                if now.duration_since(released_at) < self.thresholds.double_tap() {
+                    self.keys.insert(id, KeyState::SecondDown);
+                    None
+                } else {
+                    self.keys.insert(id, down);
+                    Some(Trigger::Tap)
+                }
+            }
+        }
+    }
+
+    pub fn released(&mut self, id: u32, now: Instant) -> Option<Trigger> {
+        match self.keys.remove(&id)? {
+            KeyState::Down { hold_fired: true, .. } => None,
+            KeyState::Down { pressed_at, bound, .. } => {
+                if bound.hold && now.duration_since(pressed_at) >= self.thresholds.hold() {
+                    Some(Trigger::Hold)
+                } else if bound.double_tap {
+                    self.keys.insert(id, KeyState::WaitingForSecondTap { released_at: now });
+                    None
+                } else {
+                    Some(Trigger::Tap)
+                }
+            }
+            KeyState::SecondDown => Some(Trigger::DoubleTap),
+            // A release without a matching press, e.g. the press happened before the binding was registered
+            KeyState::WaitingForSecondTap { released_at } => {
+                self.keys.insert(id, KeyState::WaitingForSecondTap { released_at });
+                None
+            }
+        }
+    }
+
+    // Fires holds once a key has been down long enough, and taps whose double tap window ran out.
+    // Call this regularly, e.g. whenever the event channel is empty
+    pub fn poll(&mut self, now: Instant) -> Vec<(u32, Trigger)> {
+        let thresholds = self.thresholds;
+        let mut fired = Vec::new();
+        self.keys.retain(|id, key| match key {
+            KeyState::Down { pressed_at, bound, hold_fired } => {
+                if bound.hold && !*hold_fired && now.duration_since(*pressed_at) >= thresholds.hold() {
+                    *hold_fired = true;
+                    fired.push((*id, Trigger::Hold));
+                }
+                true
+            }
+            KeyState::WaitingForSecondTap { released_at } => {
+                if now.duration_since(*released_at) >= thresholds.double_tap() {
+                    fired.push((*id, Trigger::Tap));
+                    false
+                } else {
+                    true
+                }
+            }
+            KeyState::SecondDown => true,
+        });
+        fired
+    }
+
+    // When poll has to run next to fire a hold or an expired tap, None while nothing is pending
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.keys
+            .values()
+            .filter_map(|key| match key {
+                KeyState::Down { pressed_at, bound, hold_fired: false } if bound.hold => Some(*pressed_at + self.thresholds.hold()),
+                KeyState::WaitingForSecondTap { released_at } => Some(*released_at + self.thresholds.double_tap()),
+                _ => None,
+            })
+            .min()
+    }
+}
+
+// Most steps a single hold adds up, so a hold whose release never arrives, e.g. because the key was unregistered
+// while held, cannot keep stepping forever
+const MAX_REPEAT_STEPS: u32 = 30;
+// A hold that reached MAX_REPEAT_STEPS is forgotten once the OS sent no key repeat for this long, so a lost release
+// does not leave the key ignored for good. Windows sends no key repeats, where this only ends lost holds
+const EXHAUSTED_HOLD_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy)]
+struct HeldKey {
+    action: Action,
+    next_repeat: Instant,
+    steps: u32,
+    last_pressed: Instant,
+    // Reached MAX_REPEAT_STEPS and its steps were already handed out, kept until the release so key repeats
+    // from the OS do not start a new hold
+    exhausted: bool,
+}
+
+// Counts the steps of held repeatable keys: one for the press, then one per repeat interval once the repeat delay
+// has passed, up to MAX_REPEAT_STEPS. The steps are handed out once, on release or when the limit is reached,
+// so a whole hold becomes a single request with the final target
+#[derive(Debug, Default)]
+pub struct KeyRepeater {
+    pub thresholds: PressThresholds,
+    held: HashMap<u32, HeldKey>,
+}
+
+impl KeyRepeater {
+    pub fn new(thresholds: PressThresholds) -> Self {
+        Self { thresholds, held: HashMap::new() }
+    }
+
+    pub fn is_held(&self, id: u32) -> bool {
+        self.held.contains_key(&id)
+    }
+
+    pub fn pressed(&mut self, id: u32, action: Action, now: Instant) {
+        let delay = self.thresholds.repeat_delay();
+        self.held.entry(id).or_insert(HeldKey {
+            action,
+            next_repeat: now + delay,
+            steps: 1,
+            last_pressed: now,
+            exhausted: false,
+        });
+    }
+
+    // Key repeat from the OS, returns false when the key is not being held
+    pub fn os_repeat(&mut self, id: u32, now: Instant) -> bool {
+        match self.held.get_mut(&id) {
+            Some(key) => {
+                key.last_pressed = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Ends the hold and returns the steps to run, None if there are none left, e.g. because the hold already
+    // reached the step limit or the key was not being held
+    pub fn released(&mut self, id: u32) -> Option<(Action, u32)> {
+        let key = self.held.remove(&id)?;
+        (!key.exhausted).then_some((key.action, key.steps))
+    }
+
+    // Adds the steps that are due for each held key. A late poll adds every step it missed at once.
+    // Returns the holds that just reached the step limit, since no further steps can come from them
+    pub fn poll(&mut self, now: Instant) -> Vec<(u32, Action, u32)> {
+        let interval = self.thresholds.repeat_interval().max(Duration::from_millis(1));
+        let mut fired = Vec::new();
+        self.held.retain(|id, key| {
+            if key.exhausted {
+                return now.duration_since(key.last_pressed) < EXHAUSTED_HOLD_TIMEOUT;
+            }
+            if now < key.next_repeat {
+                return true;
+            }
+            let due = 1 + (now - key.next_repeat).as_millis() / interval.as_millis();
+            let due = u32::try_from(due).unwrap_or(u32::MAX).min(MAX_REPEAT_STEPS - key.steps);
+            key.steps += due;
+            key.next_repeat += interval * due;
+            if key.steps >= MAX_REPEAT_STEPS {
+                key.exhausted = true;
+                key.last_pressed = now;
+                fired.push((*id, key.action, key.steps));
+            }
+            true
+        });
+        fired
+    }
+
+    // When poll has to run next, None while no key is held
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.held
+            .values()
+            .map(|key| if key.exhausted { key.last_pressed + EXHAUSTED_HOLD_TIMEOUT } else { key.next_repeat })
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u32 = 7;
+    const ALL: BoundTriggers = BoundTriggers { double_tap: true, hold: true };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn plain_tap_fires_on_release() {
+        let start = Instant::now();
+        let mut detector = PressDetector::default();
+        assert_eq!(detector.pressed(ID, BoundTriggers::default(), start), None);
+        assert_eq!(detector.released(ID, start + ms(50)), Some(Trigger::Tap));
+        assert!(detector.poll(start + ms(1000)).is_empty());
+    }
+
+    #[test]
+    fn tap_waits_for_double_tap_window() {
+        let start = Instant::now();
+        let mut detector = PressDetector::default();
+        detector.pressed(ID, ALL, start);
+        assert_eq!(detector.released(ID, start + ms(50)), None);
+        assert!(detector.poll(start + ms(300)).is_empty());
+        assert_eq!(detector.poll(start + ms(400)), vec![(ID, Trigger::Tap)]);
+    }
+
+    #[test]
+    fn second_tap_inside_window_is_a_double_tap() {
+        let start = Instant::now();
+        let mut detector = PressDetector::default();
+        detector.pressed(ID, ALL, start);
+        detector.released(ID, start + ms(50));
+        assert_eq!(detector.pressed(ID, ALL, start + ms(200)), None);
+        assert_eq!(detector.released(ID, start + ms(250)), Some(Trigger::DoubleTap));
+        assert!(detector.poll(start + ms(1000)).is_empty());
+    }
+
+    #[test]
+    fn late_second_press_flushes_the_first_tap() {
+        let start = Instant::now();
+        let mut detector = PressDetector::default();
+        detector.pressed(ID, ALL, start);
+        detector.released(ID, start + ms(50));
+        assert_eq!(detector.pressed(ID, ALL, start + ms(500)), Some(Trigger::Tap));
+        assert_eq!(detector.released(ID, start + ms(550)), None);
+        assert_eq!(detector.poll(start + ms(900)), vec![(ID, Trigger::Tap)]);
+    }
+
+    #[test]
+    fn hold_fires_while_down_and_release_is_ignored() {
+        let start = Instant::now();
+        let mut detector = PressDetector::default();
+        detector.pressed(ID, ALL, start);
+        assert!(detector.poll(start + ms(400)).is_empty());
+        // Key repeat must not restart the hold timer
+        detector.pressed(ID, ALL, start + ms(450));
+        assert_eq!(detector.poll(start + ms(500)), vec![(ID, Trigger::Hold)]);
+        assert!(detector.poll(start + ms(600)).is_empty());
+        assert_eq!(detector.released(ID, start + ms(700)), None);
+    }
+
+    #[test]
+    fn hold_is_detected_on_release_when_poll_was_late() {
+        let start = Instant::now();
+        let mut detector = PressDetector::default();
+        detector.pressed(ID, BoundTriggers { double_tap: false, hold: true }, start);
+        assert_eq!(detector.released(ID, start + ms(800)), Some(Trigger::Hold));
+    }
+
+    #[test]
+    fn short_press_with_only_hold_bound_is_a_tap() {
+        let start = Instant::now();
+        let mut detector = PressDetector::default();
+        detector.pressed(ID, BoundTriggers { double_tap: false, hold: true }, start);
+        assert_eq!(detector.released(ID, start + ms(100)), Some(Trigger::Tap));
+    }
+
+    #[test]
+    fn thresholds_are_configurable() {
+        let start = Instant::now();
+        let mut detector = PressDetector::new(PressThresholds { double_tap_ms: 100, hold_ms: 1000, ..Default::default() });
+        detector.pressed(ID, ALL, start);
+        assert!(detector.poll(start + ms(900)).is_empty());
+        assert_eq!(detector.released(ID, start + ms(950)), None);
+        assert_eq!(detector.pressed(ID, ALL, start + ms(1100)), Some(Trigger::Tap));
+    }
+
+    #[test]
+    fn next_deadline_follows_pending_holds_and_taps() {
+        let start = Instant::now();
+        let mut detector = PressDetector::default();
+        assert_eq!(detector.next_deadline(), None);
+
+        detector.pressed(1, ALL, start);
+        assert_eq!(detector.next_deadline(), Some(start + ms(500)));
+        detector.released(1, start + ms(50));
+        assert_eq!(detector.next_deadline(), Some(start + ms(350)));
+        assert_eq!(detector.poll(start + ms(350)), vec![(1, Trigger::Tap)]);
+        assert_eq!(detector.next_deadline(), None);
+
+        // Plain taps never need a wake up
+        detector.pressed(2, BoundTriggers::default(), start);
+        assert_eq!(detector.next_deadline(), None);
+    }
+
+    #[test]
+    fn short_press_of_repeatable_key_is_one_step() {
+        let start = Instant::now();
+        let mut repeater = KeyRepeater::default();
+        repeater.pressed(ID, Action::VolumeUp, start);
+        assert!(repeater.is_held(ID));
+        assert!(repeater.poll(start + ms(100)).is_empty());
+        assert_eq!(repeater.released(ID), Some((Action::VolumeUp, 1)));
+        assert!(!repeater.is_held(ID));
+        assert_eq!(repeater.next_deadline(), None);
+    }
+
+    #[test]
+    fn held_key_adds_steps_after_delay_at_interval() {
+        let start = Instant::now();
+        let mut repeater = KeyRepeater::default();
+        repeater.pressed(ID, Action::VolumeUp, start);
+        // OS key repeat must not restart the hold
+        assert!(repeater.os_repeat(ID, start + ms(300)));
+        repeater.pressed(ID, Action::VolumeUp, start + ms(300));
+        assert_eq!(repeater.next_deadline(), Some(start + ms(400)));
+        assert!(repeater.poll(start + ms(400)).is_empty());
+        assert_eq!(repeater.next_deadline(), Some(start + ms(500)));
+        assert!(repeater.poll(start + ms(450)).is_empty());
+        // A late poll catches up on the steps it missed
+        assert!(repeater.poll(start + ms(750)).is_empty());
+        assert_eq!(repeater.next_deadline(), Some(start + ms(800)));
+        // The whole hold is handed out at once
+        assert_eq!(repeater.released(ID), Some((Action::VolumeUp, 5)));
+    }
+
+    #[test]
+    fn repeat_timing_is_configurable() {
+        let start = Instant::now();
+        let mut repeater = KeyRepeater::new(PressThresholds { repeat_delay_ms: 200, repeat_interval_ms: 50, ..Default::default() });
+        repeater.pressed(ID, Action::SeekForward, start);
+        repeater.poll(start + ms(400));
+        assert_eq!(repeater.released(ID), Some((Action::SeekForward, 6)));
+    }
+
+    #[test]
+    fn hold_is_handed_out_once_at_the_step_limit() {
+        let start = Instant::now();
+        let mut repeater = KeyRepeater::default();
+        repeater.pressed(ID, Action::VolumeDown, start);
+        let limit = start + ms(60_000);
+        assert_eq!(repeater.poll(limit), vec![(ID, Action::VolumeDown, MAX_REPEAT_STEPS)]);
+
+        // Key repeats from the OS keep hitting the exhausted hold instead of starting a new one
+        assert!(repeater.os_repeat(ID, limit + ms(4_000)));
+        assert!(repeater.poll(limit + ms(8_000)).is_empty());
+        assert!(repeater.is_held(ID));
+        assert_eq!(repeater.released(ID), None);
+        assert!(!repeater.is_held(ID));
+    }
+
+    #[test]
+    fn exhausted_hold_without_release_is_forgotten() {
+        let start = Instant::now();
+        let mut repeater = KeyRepeater::default();
+        repeater.pressed(ID, Action::VolumeDown, start);
+        let limit = start + ms(60_000);
+        repeater.poll(limit);
+        assert_eq!(repeater.next_deadline(), Some(limit + EXHAUSTED_HOLD_TIMEOUT));
+        assert!(repeater.poll(limit + EXHAUSTED_HOLD_TIMEOUT).is_empty());
+        assert!(!repeater.is_held(ID));
+        assert_eq!(repeater.next_deadline(), None);
+    }
+
+    #[test]
+    fn release_without_press_is_ignored() {
+        let mut repeater = KeyRepeater::default();
+        assert_eq!(repeater.released(ID), None);
+        assert!(!repeater.os_repeat(ID, Instant::now()));
+    }
+
+    #[test]
+    fn keys_are_tracked_independently() {
+        let start = Instant::now();
+        let mut detector = PressDetector::default();
+        detector.pressed(1, ALL, start);
+        detector.pressed(2, BoundTriggers::default(), start + ms(10));
+        assert_eq!(detector.released(2, start + ms(20)), Some(Trigger::Tap));
+        assert_eq!(detector.released(1, start + ms(30)), None);
+        assert_eq!(detector.poll(start + ms(400)), vec![(1, Trigger::Tap)]);
+    }
+}
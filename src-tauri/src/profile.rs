@@ -0,0 +1,177 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::api::AuthResult;
+use crate::hotkey::{apply_bindings, validate_bindings, BindingResult};
+use crate::hotkey_cache::HotkeyCache;
+use crate::AppState;
+
+// Returned to the frontend so it can render the profile picker
+#[derive(Serialize, Debug, Clone)]
+pub struct ProfileList {
+    pub active: String,
+    pub profiles: Vec<String>,
+}
+
+impl From<&HotkeyCache> for ProfileList {
+    fn from(cache: &HotkeyCache) -> Self {
+        Self {
+            active: cache.active_profile.clone(),
+            profiles: cache.profiles.keys().cloned().collect(),
+        }
+    }
+}
+
+fn validate_profile_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Profile name cannot be empty".to_string());
+    }
+    Ok(name.to_string())
+}
+
+#[tauri::command]
+pub async fn list_profiles() -> Result<ProfileList, String> {
+    let cache = HotkeyCache::load_or_default(HotkeyCache::path())?;
+    Ok(ProfileList::from(&cache))
+}
+
+// Creates an empty profile, or a copy of `copy_from` when given. Global bindings are shared so they are never copied
+#[tauri::command]
+pub async fn create_profile(
+    state: State<'_, AppState>,
+    name: String,
+    copy_from: Option<String>,
+) -> Result<ProfileList, String> {
+    let name = validate_profile_name(&name)?;
+
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    let profiles = HotkeyCache::update(&hotkey_map_guard, |cache| {
+        if cache.profiles.contains_key(&name) {
+            return Err(format!("Profile '{}' already exists", name));
+        }
+
+        let profile = match &copy_from {
+            Some(source) => cache
+                .profiles
+                .get(source)
+                .cloned()
+                .ok_or_else(|| format!("Profile '{}' does not exist", source))?,
+            None => Default::default(),
+        };
+        cache.profiles.insert(name.clone(), profile);
+        Ok(ProfileList::from(&*cache))
+    })?;
+
+    log::info!("Create_Profile: Created profile '{}'", name);
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub async fn rename_profile(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+    new_name: String,
+) -> Result<ProfileList, String> {
+    let new_name = validate_profile_name(&new_name)?;
+
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    if name == new_name {
+        return Ok(ProfileList::from(&HotkeyCache::load_or_default(HotkeyCache::path())?));
+    }
+    let profiles = HotkeyCache::update(&hotkey_map_guard, |cache| {
+        if cache.profiles.contains_key(&new_name) {
+            return Err(format!("Profile '{}' already exists", new_name));
+        }
+        let profile = cache
+            .profiles
+            .remove(&name)
+            .ok_or_else(|| format!("Profile '{}' does not exist", name))?;
+        cache.profiles.insert(new_name.clone(), profile);
+
+        if cache.active_profile == name {
+            cache.active_profile = new_name.clone();
+        }
+        Ok(ProfileList::from(&*cache))
+    })?;
+
+    log::info!("Rename_Profile: Renamed profile '{}' to '{}'", name, new_name);
+    let _ = app_handle.emit("hotkey-profile-changed", &profiles);
+    Ok(profiles)
+}
+
+// The active profile cannot be deleted, which also means the last remaining profile never can
+#[tauri::command]
+pub async fn delete_profile(state: State<'_, AppState>, name: String) -> Result<ProfileList, String> {
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    let profiles = HotkeyCache::update(&hotkey_map_guard, |cache| {
+        if cache.active_profile == name {
+            return Err(format!("Profile '{}' is active, switch to another profile before deleting it", name));
+        }
+        if cache.profiles.remove(&name).is_none() {
+            return Err(format!("Profile '{}' does not exist", name));
+        }
+        Ok(ProfileList::from(&*cache))
+    })?;
+
+    log::info!("Delete_Profile: Deleted profile '{}'", name);
+    Ok(profiles)
+}
+
+#[tauri::command]
+pub async fn activate_profile(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    name: String,
+) -> Result<Vec<BindingResult>, String> {
+    switch_profile(&app_handle, &state, &name).await
+}
+
+// Switches to the profile after the active one, wrapping around. Bound to Action::CycleProfile
+pub async fn cycle_profile(app_handle: &AppHandle) -> Result<AuthResult, String> {
+    let cache = HotkeyCache::load_or_default(HotkeyCache::path())?;
+    let next = cache
+        .profiles
+        .keys()
+        .skip_while(|name| **name != cache.active_profile)
+        .nth(1)
+        .or_else(|| cache.profiles.keys().next())
+        .cloned()
+        .ok_or("No hotkey profiles exist")?;
+
+    let results = switch_profile(app_handle, &app_handle.state(), &next).await?;
+    if let Some(failed) = results.iter().find(|result| result.error.is_some()) {
+        return Ok(AuthResult::Error {
+            message: failed.message.clone().unwrap_or_default(),
+        });
+    }
+    Ok(AuthResult::Success {
+        ok: format!("Switched to profile '{}'", next),
+    })
+}
+
+// Validates the target profile before touching anything, so a broken profile leaves the current one registered.
+// Registration then goes through apply_bindings, the same path set_hotkeys uses, which also saves the switch
+async fn switch_profile(
+    app_handle: &AppHandle,
+    state: &State<'_, AppState>,
+    name: &str,
+) -> Result<Vec<BindingResult>, String> {
+    let cache = HotkeyCache::load_or_default(HotkeyCache::path())?;
+    let bindings = cache
+        .profile_bindings(name)
+        .ok_or_else(|| format!("Profile '{}' does not exist", name))?;
+
+    let (_, results) = validate_bindings(bindings.clone(), &cache.macros);
+    if results.iter().any(|result| result.error.is_some()) {
+        log::warn!("Switch_Profile: Profile '{}' has invalid bindings, not switching", name);
+        return Ok(results);
+    }
+
+    let results = apply_bindings(app_handle, state, bindings, Some(name)).await?;
+    log::info!("Switch_Profile: Activated profile '{}'", name);
+    let cache = HotkeyCache::load_or_default(HotkeyCache::path())?;
+    let _ = app_handle.emit("hotkey-profile-changed", ProfileList::from(&cache));
+    Ok(results)
+}
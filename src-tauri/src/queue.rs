@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager};
+use tokio::sync::oneshot;
+
+use crate::action::Action;
+use crate::activity::{action_outcome, finish_activity, ActivityOutcome};
+use crate::macros::{run_step, StepCommand};
+use crate::AppState;
+
+// Actions that waited longer than this are skipped, pressing next a few seconds ago should not skip a track now
+const STALE_ACTION_AGE: Duration = Duration::from_secs(2);
+// Oldest actions are dropped once this many are waiting
+const MAX_QUEUED_ACTIONS: usize = 8;
+
+// What the action worker runs for one queue entry
+#[derive(Debug)]
+pub enum QueuedJob {
+    Action {
+        action: Action,
+        steps: i32,
+        // Activity log entries of every press merged into this job
+        activity: Vec<u64>,
+    },
+    // One step of a running macro, which waits for the result on `done`. Never merged with anything
+    MacroStep { command: StepCommand, done: oneshot::Sender<Result<(), String>> },
+}
+
+#[derive(Debug)]
+struct QueuedEntry {
+    job: QueuedJob,
+    queued_at: Instant,
+}
+
+// Spotify actions waiting to be executed, in order. A new action is merged into the last queued one when
+// they coalesce, so a burst of presses that arrives while a request is in flight becomes a single job
+#[derive(Debug, Default)]
+pub struct ActionQueue {
+    queued: VecDeque<QueuedEntry>,
+    // Activity entries of presses that will never run, with their action and the reason
+    discarded: Vec<(u64, Action, &'static str)>,
+}
+
+impl ActionQueue {
+    pub fn push(&mut self, action: Action, steps: i32, activity: u64, now: Instant) {
+        if let Some(last) = self.queued.back_mut()
+            && let QueuedJob::Action { action: last_action, steps: last_steps, activity: last_activity } = &mut last.job
+            && let Some(direction) = last_action.coalesce(action)
+        {
+            // queued_at stays at the first press, so a steady stream of merges cannot keep a job from going stale
+            *last_steps += direction * steps;
+            last_activity.push(activity);
+            // E.g. volume up followed by volume down
+            if *last_steps == 0
+                && let Some(cancelled) = self.queued.pop_back()
+            {
+                self.discard(cancelled.job, "cancelled out by an opposite press");
+            }
+            return;
+        }
+
+        self.push_job(QueuedJob::Action { action, steps, activity: vec![activity] }, now);
+    }
+
+    // Macro steps take their turn like hotkey presses, so they never run at the same time as a queued action
+    pub fn push_macro_step(&mut self, command: StepCommand, done: oneshot::Sender<Result<(), String>>, now: Instant) {
+        self.push_job(QueuedJob::MacroStep { command, done }, now);
+    }
+
+    // Next job to run, skipping the ones that went stale while waiting
+    pub fn pop(&mut self, now: Instant) -> Option<QueuedJob> {
+        while let Some(next) = self.queued.pop_front() {
+            if now.duration_since(next.queued_at) > STALE_ACTION_AGE {
+                log::warn!("Action_Queue: Dropping stale {}", describe_job(&next.job));
+                self.discard(next.job, "waited too long in the action queue");
+                continue;
+            }
+            return Some(next.job);
+        }
+        None
+    }
+
+    pub fn take_discarded(&mut self) -> Vec<(u64, Action, &'static str)> {
+        std::mem::take(&mut self.discarded)
+    }
+
+    fn push_job(&mut self, job: QueuedJob, now: Instant) {
+        self.queued.push_back(QueuedEntry { job, queued_at: now });
+        if self.queued.len() > MAX_QUEUED_ACTIONS
+            && let Some(dropped) = self.queued.pop_front()
+        {
+            log::warn!("Action_Queue: Queue is full, dropping {}", describe_job(&dropped.job));
+            self.discard(dropped.job, "action queue was full");
+        }
+    }
+
+    // A dropped macro step is failed right away, its macro decides whether to go on
+    fn discard(&mut self, job: QueuedJob, reason: &'static str) {
+        match job {
+            QueuedJob::Action { action, activity, .. } => {
+                self.discarded.extend(activity.into_iter().map(|activity| (activity, action, reason)));
+            }
+            QueuedJob::MacroStep { done, .. } => {
+                let _ = done.send(Err(format!("Step was dropped: {}", reason)));
+            }
+        }
+    }
+}
+
+fn describe_job(job: &QueuedJob) -> String {
+    match job {
+        QueuedJob::Action { action, steps, .. } => format!("{} x{}", action.label(), steps),
+        QueuedJob::MacroStep { command, .. } => format!("macro step {:?}", command),
+    }
+}
+
+async fn finish_discarded(app_handle: &AppHandle, discarded: Vec<(u64, Action, &'static str)>) {
+    for (activity, action, reason) in discarded {
+        let outcome = ActivityOutcome::Dropped { reason: reason.to_string() };
+        finish_activity(app_handle, activity, Some(action), outcome).await;
+    }
+}
+
+// Queues a Spotify action for the worker, returns immediately
+pub async fn enqueue_action(app_handle: &AppHandle, action: Action, steps: i32, activity: u64) {
+    let state = app_handle.state::<AppState>();
+    let discarded = {
+        let mut action_queue = state.action_queue.lock().await;
+        action_queue.push(action, steps, activity, Instant::now());
+        action_queue.take_discarded()
+    };
+    state.action_queue_notify.notify_one();
+    finish_discarded(app_handle, discarded).await;
+}
+
+// Queues one macro step for the worker and waits until it ran
+pub async fn run_queued_macro_step(app_handle: &AppHandle, command: StepCommand) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    let (done_tx, done_rx) = oneshot::channel();
+    state.action_queue.lock().await.push_macro_step(command, done_tx, Instant::now());
+    state.action_queue_notify.notify_one();
+    done_rx.await.map_err(|_| "Action worker stopped before running the step".to_string())?
+}
+
+// Runs queued actions one at a time for the lifetime of the app, so hotkeys never wait on the Spotify API
+pub fn spawn_action_worker(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        log::info!("Action_Worker: Starting action worker");
+        let state = app_handle.state::<AppState>();
+        loop {
+            let (next, discarded) = {
+                let mut action_queue = state.action_queue.lock().await;
+                (action_queue.pop(Instant::now()), action_queue.take_discarded())
+            };
+            finish_discarded(&app_handle, discarded).await;
+            let Some(job) = next else {
+                state.action_queue_notify.notified().await;
+                continue;
+            };
+
+            log::debug!("Action_Worker: Running {}", describe_job(&job));
+            match job {
+                QueuedJob::Action { action, steps, activity } => {
+                    let outcome = action_outcome(action, action.execute_repeated(&app_handle, steps).await);
+                    for activity in activity {
+                        finish_activity(&app_handle, activity, Some(action), outcome.clone()).await;
+                    }
+                }
+                QueuedJob::MacroStep { command, done } => {
+                    let _ = done.send(run_step(&app_handle, &command).await);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_action(queue: &mut ActionQueue, now: Instant) -> Option<(Action, i32, Vec<u64>)> {
+        match queue.pop(now)? {
+            QueuedJob::Action { action, steps, activity } => Some((action, steps, activity)),
+            QueuedJob::MacroStep { command, .. } => panic!("Expected an action, got macro step {:?}", command),
+        }
+    }
+
+    fn drain(queue: &mut ActionQueue, now: Instant) -> Vec<(Action, i32)> {
+        std::iter::from_fn(|| pop_action(queue, now)).map(|(action, steps, _)| (action, steps)).collect()
+    }
+
+    #[test]
+    fn bursts_of_the_same_action_collapse() {
+        let now = Instant::now();
+        let mut queue = ActionQueue::default();
+        for _ in 0..5 {
+            queue.push(Action::NextTrack, 1, 0, now);
+        }
+        for _ in 0..10 {
+            queue.push(Action::VolumeUp, 1, 0, now);
+        }
+        assert_eq!(drain(&mut queue, now), vec![(Action::NextTrack, 5), (Action::VolumeUp, 10)]);
+    }
+
+    #[test]
+    fn opposite_steps_cancel_out() {
+        let now = Instant::now();
+        let mut queue = ActionQueue::default();
+        queue.push(Action::VolumeUp, 3, 0, now);
+        queue.push(Action::VolumeDown, 1, 0, now);
+        queue.push(Action::SeekForward, 1, 0, now);
+        queue.push(Action::SeekBackward, 1, 0, now);
+        assert_eq!(drain(&mut queue, now), vec![(Action::VolumeUp, 2)]);
+    }
+
+    #[test]
+    fn order_is_kept_and_toggles_never_merge() {
+        let now = Instant::now();
+        let mut queue = ActionQueue::default();
+        queue.push(Action::PlayPause, 1, 0, now);
+        queue.push(Action::PlayPause, 1, 0, now);
+        queue.push(Action::NextTrack, 1, 0, now);
+        queue.push(Action::PrevTrack, 1, 0, now);
+        assert_eq!(
+            drain(&mut queue, now),
+            vec![(Action::PlayPause, 1), (Action::PlayPause, 1), (Action::NextTrack, 1), (Action::PrevTrack, 1)]
+        );
+    }
+
+    #[test]
+    fn stale_actions_are_dropped() {
+        let start = Instant::now();
+        let mut queue = ActionQueue::default();
+        queue.push(Action::NextTrack, 1, 0, start);
+        queue.push(Action::PlayPause, 1, 0, start + Duration::from_secs(2));
+        assert_eq!(drain(&mut queue, start + Duration::from_secs(3)), vec![(Action::PlayPause, 1)]);
+    }
+
+    #[test]
+    fn merging_does_not_keep_a_job_fresh() {
+        let start = Instant::now();
+        let mut queue = ActionQueue::default();
+        for second in 0..4 {
+            queue.push(Action::VolumeUp, 1, second, start + Duration::from_secs(second));
+        }
+        assert_eq!(pop_action(&mut queue, start + Duration::from_secs(4)), None);
+        assert_eq!(queue.take_discarded().len(), 4);
+    }
+
+    #[test]
+    fn merged_and_dropped_presses_keep_their_activity() {
+        let start = Instant::now();
+        let mut queue = ActionQueue::default();
+        queue.push(Action::VolumeUp, 1, 1, start);
+        queue.push(Action::VolumeDown, 1, 2, start);
+        queue.push(Action::NextTrack, 1, 3, start);
+        queue.push(Action::NextTrack, 1, 4, start);
+        assert_eq!(pop_action(&mut queue, start), Some((Action::NextTrack, 2, vec![3, 4])));
+
+        queue.push(Action::PlayPause, 1, 5, start);
+        assert_eq!(pop_action(&mut queue, start + Duration::from_secs(3)), None);
+        let discarded: Vec<u64> = queue.take_discarded().into_iter().map(|(activity, _, _)| activity).collect();
+        assert_eq!(discarded, vec![1, 2, 5]);
+    }
+
+    #[test]
+    fn full_queue_drops_the_oldest() {
+        let now = Instant::now();
+        let mut queue = ActionQueue::default();
+        for _ in 0..MAX_QUEUED_ACTIONS {
+            queue.push(Action::PlayPause, 1, 0, now);
+        }
+        queue.push(Action::LikeTrack, 1, 0, now);
+        let drained = drain(&mut queue, now);
+        assert_eq!(drained.len(), MAX_QUEUED_ACTIONS);
+        assert_eq!(drained.last(), Some(&(Action::LikeTrack, 1)));
+    }
+
+    #[test]
+    fn macro_steps_keep_their_turn_and_never_merge() {
+        let now = Instant::now();
+        let mut queue = ActionQueue::default();
+        let (done_tx, _done_rx) = oneshot::channel();
+        queue.push(Action::VolumeUp, 1, 0, now);
+        queue.push_macro_step(StepCommand::Action { action: Action::VolumeUp }, done_tx, now);
+        queue.push(Action::VolumeUp, 1, 0, now);
+
+        assert_eq!(pop_action(&mut queue, now), Some((Action::VolumeUp, 1, vec![0])));
+        assert!(matches!(queue.pop(now), Some(QueuedJob::MacroStep { .. })));
+        assert_eq!(pop_action(&mut queue, now), Some((Action::VolumeUp, 1, vec![0])));
+    }
+
+    #[test]
+    fn dropped_macro_steps_report_why() {
+        let start = Instant::now();
+        let mut queue = ActionQueue::default();
+        let (done_tx, mut done_rx) = oneshot::channel();
+        queue.push_macro_step(StepCommand::SetVolume { percent: 40 }, done_tx, start);
+
+        assert!(queue.pop(start + Duration::from_secs(3)).is_none());
+        let result = done_rx.try_recv().unwrap();
+        assert_eq!(result, Err("Step was dropped: waited too long in the action queue".to_string()));
+    }
+}
@@ -0,0 +1,125 @@
+use global_hotkey::hotkey::{Code, HotKey};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::oneshot;
+
+use crate::accelerator::{format_hotkey, recordable_hotkeys};
+use crate::hotkey::{desired_hotkeys, sync_registered_hotkeys};
+use crate::AppState;
+
+const DEFAULT_RECORDING_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RECORDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+// A running recording. Every combo from recordable_hotkeys is registered with the OS until it ends, and the
+// listener hands the first one pressed to `recorded_tx`. Pressing Escape on its own cancels
+pub struct HotkeyRecording {
+    candidates: HashMap<u32, HotKey>,
+    recorded_tx: Option<oneshot::Sender<HotKey>>,
+}
+
+// Payload of "hotkey-recorded"
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordingResult {
+    Recorded { accelerator: String },
+    TimedOut,
+    Cancelled,
+}
+
+// Starts capturing the next combo pressed anywhere, the result is emitted as "hotkey-recorded"
+#[tauri::command]
+pub async fn record_hotkey(app_handle: AppHandle, timeout_ms: Option<u64>) -> Result<(), String> {
+    let timeout = timeout_ms.map_or(DEFAULT_RECORDING_TIMEOUT, Duration::from_millis).min(MAX_RECORDING_TIMEOUT);
+    let state = app_handle.state::<AppState>();
+
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    let mut recording = state.hotkey_recording.lock().await;
+    if recording.is_some() {
+        return Err("Already recording a hotkey".to_string());
+    }
+    let Some(hotkey_map) = hotkey_map_guard.as_ref() else {
+        log::error!("Record_Hotkey: Hotkey hashmap is not initialized!");
+        return Err("Hotkey hashmap is not initialized".to_string());
+    };
+
+    // Current bindings stay registered so they can be recorded again, pressing them does not run their action
+    let candidates = recordable_hotkeys();
+    let mut desired = desired_hotkeys(hotkey_map, *state.hotkeys_suspended.lock().await);
+    desired.extend(candidates.iter().copied());
+    let failures = sync_registered_hotkeys(&app_handle, desired).await?;
+    if !failures.is_empty() {
+        log::warn!("Record_Hotkey: {} combos are taken by other apps and cannot be recorded", failures.len());
+    }
+
+    let (recorded_tx, recorded_rx) = oneshot::channel();
+    *recording = Some(HotkeyRecording {
+        candidates: candidates
+            .into_iter()
+            .filter(|hotkey| !failures.contains_key(hotkey))
+            .map(|hotkey| (hotkey.id(), hotkey))
+            .collect(),
+        recorded_tx: Some(recorded_tx),
+    });
+    drop(recording);
+    drop(hotkey_map_guard);
+    log::info!("Record_Hotkey: Recording the next hotkey for up to {:?}", timeout);
+
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let result = match tokio::time::timeout(timeout, recorded_rx).await {
+            Ok(Ok(hotkey)) => RecordingResult::Recorded { accelerator: format_hotkey(&hotkey) },
+            Ok(Err(_)) => RecordingResult::Cancelled,
+            Err(_) => RecordingResult::TimedOut,
+        };
+        finish_recording(&app_handle).await;
+
+        log::info!("Record_Hotkey: Recording ended: {:?}", result);
+        if let Err(e) = app_handle.emit("hotkey-recorded", result) {
+            log::error!("Record_Hotkey: Failed to emit recorded hotkey: {}", e);
+        }
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn cancel_hotkey_recording(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    // Dropping the sender ends the recording as cancelled
+    if let Some(recording) = state.hotkey_recording.lock().await.as_mut() {
+        recording.recorded_tx.take();
+    }
+    Ok(())
+}
+
+// Called by the listener for every press, returns true when a recording took it
+pub async fn capture_recorded_hotkey(app_handle: &AppHandle, hotkey_id: u32) -> bool {
+    let state = app_handle.state::<AppState>();
+    let mut recording = state.hotkey_recording.lock().await;
+    let Some(recording) = recording.as_mut() else {
+        return false;
+    };
+
+    if let Some(hotkey) = recording.candidates.get(&hotkey_id).copied()
+        && let Some(recorded_tx) = recording.recorded_tx.take()
+        && hotkey != HotKey::new(None, Code::Escape)
+    {
+        let _ = recorded_tx.send(hotkey);
+    }
+    true
+}
+
+// Drops the recording and puts the registered hotkeys back to the bindings
+async fn finish_recording(app_handle: &AppHandle) {
+    let state = app_handle.state::<AppState>();
+    let hotkey_map_guard = state.hotkey_hashmap.lock().await;
+    state.hotkey_recording.lock().await.take();
+    let Some(hotkey_map) = hotkey_map_guard.as_ref() else {
+        return;
+    };
+
+    let desired = desired_hotkeys(hotkey_map, *state.hotkeys_suspended.lock().await);
+    if let Err(e) = sync_registered_hotkeys(app_handle, desired).await {
+        log::error!("Finish_Recording: Failed to restore hotkeys: {}", e);
+    }
+}
@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use rspotify::prelude::*;
+use tauri::{State, Url};
+
+use crate::api::init_spotify;
+use crate::persist::{quarantine_corrupt, write_atomic};
+use crate::{AppState, APP_CACHE_DIR, SETTINGS_FILE};
+
+// App settings that are not part of a hotkey profile
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Settings {
+    #[serde(default)]
+    pub spotify: SpotifySettings,
+}
+
+// Redirect URI registered for the built-in Spotify app
+pub const DEFAULT_REDIRECT_URI: &str = "http://localhost:8888/callback";
+// Default for a custom client id, the Spotify dashboard only accepts loopback IP redirect URIs for new apps
+pub const CUSTOM_CLIENT_REDIRECT_URI: &str = "http://127.0.0.1:8888/callback";
+const MAX_CALLBACK_PORTS: usize = 10;
+
+// Lets users register their own Spotify app instead of sharing the built-in one and its quota.
+// None falls back to the built-in client id and redirect URI
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct SpotifySettings {
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub redirect_uri: Option<String>,
+    // Ports the login callback server tries in order, empty means only the port of the redirect URI.
+    // Every one of them has to be registered as a redirect URI of the Spotify app
+    #[serde(default)]
+    pub callback_ports: Vec<u16>,
+}
+
+impl SpotifySettings {
+    pub fn redirect_uri(&self) -> &str {
+        match (&self.redirect_uri, &self.client_id) {
+            (Some(redirect_uri), _) => redirect_uri,
+            (None, Some(_)) => CUSTOM_CLIENT_REDIRECT_URI,
+            (None, None) => DEFAULT_REDIRECT_URI,
+        }
+    }
+
+    pub fn callback_ports(&self) -> Vec<u16> {
+        if !self.callback_ports.is_empty() {
+            return self.callback_ports.clone();
+        }
+        let port = Url::parse(self.redirect_uri()).ok().and_then(|url| url.port_or_known_default());
+        vec![port.unwrap_or(8888)]
+    }
+
+    // The redirect URI with the port the callback server ended up on
+    pub fn redirect_uri_for_port(&self, port: u16) -> String {
+        let Ok(mut url) = Url::parse(self.redirect_uri()) else {
+            return self.redirect_uri().to_string();
+        };
+        match url.set_port(Some(port)) {
+            Ok(()) => url.to_string(),
+            Err(()) => self.redirect_uri().to_string(),
+        }
+    }
+}
+
+impl Settings {
+    // Missing or unreadable settings fall back to the defaults, a corrupt file is kept aside as `.corrupt`
+    pub fn load(path: &Path) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                log::error!("Settings: Failed to read settings: {}", e);
+                return Self::default();
+            }
+        };
+        serde_json::from_str(&contents).unwrap_or_else(|e| {
+            log::error!("Settings: Settings are corrupt, using defaults: {}", e);
+            if let Err(e) = quarantine_corrupt(path) {
+                log::error!("Settings: Failed to quarantine corrupt settings: {}", e);
+            }
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        write_atomic(path, json.as_bytes()).map_err(|e| e.to_string())
+    }
+}
+
+pub fn settings_path() -> PathBuf {
+    APP_CACHE_DIR
+        .get()
+        .expect("settings: APP_CACHE_DIR not initialized")
+        .join(SETTINGS_FILE)
+}
+
+// Blank values mean "use the built-in one"
+fn normalize(value: Option<String>) -> Option<String> {
+    value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+fn validate_spotify_settings(settings: &SpotifySettings) -> Result<(), String> {
+    if let Some(client_id) = &settings.client_id
+        && (client_id.len() != 32 || !client_id.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return Err("Client ID should be the 32 character id from the Spotify developer dashboard".to_string());
+    }
+
+    // The callback server only listens on the loopback interface
+    if let Some(redirect_uri) = &settings.redirect_uri {
+        let url = Url::parse(redirect_uri).map_err(|e| format!("Redirect URI is not a valid URL: {}", e))?;
+        if url.scheme() != "http" || !matches!(url.host_str(), Some("127.0.0.1" | "localhost")) || url.path() != "/callback" {
+            return Err("Redirect URI must look like http://127.0.0.1:<port>/callback".to_string());
+        }
+    }
+
+    let ports = &settings.callback_ports;
+    if ports.len() > MAX_CALLBACK_PORTS {
+        return Err(format!("At most {} callback ports can be set", MAX_CALLBACK_PORTS));
+    }
+    if ports.contains(&0) {
+        return Err("Callback port 0 is not a valid port".to_string());
+    }
+    if let Some((index, port)) = ports.iter().enumerate().find(|(index, port)| ports[..*index].contains(port)) {
+        return Err(format!("Callback port {} is listed twice (entry {})", port, index + 1));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_spotify_settings() -> Result<SpotifySettings, String> {
+    Ok(Settings::load(&settings_path()).spotify)
+}
+
+// Saves the new client settings and swaps in a Spotify client built from them. When the client id changes the
+// cached token belongs to the old client, so it is deleted and the user has to log in again
+#[tauri::command]
+pub async fn set_spotify_settings(
+    state: State<'_, AppState>,
+    client_id: Option<String>,
+    redirect_uri: Option<String>,
+    callback_ports: Vec<u16>,
+) -> Result<SpotifySettings, String> {
+    let spotify_settings =
+        SpotifySettings { client_id: normalize(client_id), redirect_uri: normalize(redirect_uri), callback_ports };
+    validate_spotify_settings(&spotify_settings)?;
+
+    let mut spotify = state.spotify.lock().await;
+    let mut settings = Settings::load(&settings_path());
+    if settings.spotify == spotify_settings {
+        return Ok(spotify_settings);
+    }
+    let client_changed = settings.spotify.client_id != spotify_settings.client_id;
+    settings.spotify = spotify_settings.clone();
+    settings.save(&settings_path())?;
+
+    if client_changed
+        && let Some(old_spotify) = spotify.as_ref()
+    {
+        match fs::remove_file(&old_spotify.config.cache_path) {
+            Ok(()) => log::info!("Set_Spotify_Settings: Cleared token cache of the previous client"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => log::error!("Set_Spotify_Settings: Failed to clear token cache: {}", e),
+        }
+    }
+    // The token lives in the client, keep it when only the redirect settings changed
+    let token = match spotify.as_ref() {
+        Some(old_spotify) if !client_changed => old_spotify.get_token().lock().await.unwrap().clone(),
+        _ => None,
+    };
+    let new_spotify = init_spotify(&settings.spotify);
+    *new_spotify.get_token().lock().await.unwrap() = token;
+    *spotify = Some(new_spotify);
+    log::info!(
+        "Set_Spotify_Settings: Using {} client id",
+        if settings.spotify.client_id.is_some() { "a custom" } else { "the built-in" }
+    );
+    Ok(spotify_settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callback_ports_default_to_the_redirect_uri_port() {
+        let mut settings = SpotifySettings::default();
+        assert_eq!(settings.callback_ports(), vec![8888]);
+
+        settings.redirect_uri = Some("http://127.0.0.1:9000/callback".to_string());
+        assert_eq!(settings.callback_ports(), vec![9000]);
+
+        settings.callback_ports = vec![8890, 8891];
+        assert_eq!(settings.callback_ports(), vec![8890, 8891]);
+        assert_eq!(settings.redirect_uri_for_port(8891), "http://127.0.0.1:8891/callback");
+    }
+
+    #[test]
+    fn custom_clients_default_to_a_loopback_ip_redirect() {
+        let mut settings = SpotifySettings::default();
+        assert_eq!(settings.redirect_uri(), DEFAULT_REDIRECT_URI);
+
+        settings.client_id = Some("0123456789abcdef0123456789abcdef".to_string());
+        assert_eq!(settings.redirect_uri(), "http://127.0.0.1:8888/callback");
+        assert_eq!(settings.redirect_uri_for_port(8889), "http://127.0.0.1:8889/callback");
+    }
+
+    #[test]
+    fn invalid_callback_ports_are_rejected() {
+        let settings = |callback_ports| SpotifySettings { callback_ports, ..Default::default() };
+        assert!(validate_spotify_settings(&settings(vec![8888, 8889])).is_ok());
+        assert!(validate_spotify_settings(&settings(vec![8888, 0])).is_err());
+        assert!(validate_spotify_settings(&settings(vec![8888, 8889, 8888])).is_err());
+        assert!(validate_spotify_settings(&settings((1..=11).collect())).is_err());
+    }
+}